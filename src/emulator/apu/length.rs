@@ -0,0 +1,57 @@
+pub struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> Self {
+        Self {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    /// Loads the counter from the length bits of NRx1
+    pub fn load(&mut self, length: u8) {
+        self.counter = self.max - u16::from(length);
+    }
+
+    /// Clocked by the frame sequencer, returns true if the channel should be disabled
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+
+    /// Handles a write to NRx4, returns true if the channel should be disabled
+    ///
+    /// If the next frame sequencer step won't clock the counter, enabling it or triggering with
+    /// it enabled clocks it once straight away
+    pub fn write_control(&mut self, enable: bool, trigger: bool, next_step_clocks: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enable;
+
+        let mut disable = false;
+        if !was_enabled && enable && !next_step_clocks && self.counter > 0 {
+            self.counter -= 1;
+            disable = self.counter == 0 && !trigger;
+        }
+
+        if trigger && self.counter == 0 {
+            self.counter = self.max;
+            if enable && !next_step_clocks {
+                self.counter -= 1;
+            }
+        }
+        disable
+    }
+
+    // The counter itself survives the APU being powered off on the DMG
+    pub fn power_off(&mut self) {
+        self.enabled = false;
+    }
+}
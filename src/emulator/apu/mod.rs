@@ -0,0 +1,318 @@
+mod envelope;
+mod length;
+mod noise;
+mod pulse;
+mod wave;
+
+use crate::{emulator::CPU_FREQ, utils::bit_ops::BitOps};
+use noise::NoiseChannel;
+use pulse::PulseChannel;
+use wave::WaveChannel;
+
+// T-cycles between output samples
+const SAMPLE_PERIOD: usize = 64;
+pub const APU_SAMPLE_RATE: u32 = (CPU_FREQ / SAMPLE_PERIOD) as u32;
+// Samples nobody collects are dropped past one second's worth
+const MAX_BUFFERED_SAMPLES: usize = APU_SAMPLE_RATE as usize;
+// How much charge the output capacitor keeps per sample, removes the DACs' DC offset
+const HIGH_PASS_CHARGE: f32 = 0.9973;
+
+const NR10: u16 = 0xFF10;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+
+// Bits of NR10..=NR52 that can't be read back and always read as 1
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+pub struct Apu {
+    power: bool,
+    // Last value written to each register, NR52 is built on read
+    registers: [u8; 0x17],
+    // Next step of the 512Hz frame sequencer
+    frame_step: u8,
+    ch1: PulseChannel,
+    ch2: PulseChannel,
+    ch3: WaveChannel,
+    ch4: NoiseChannel,
+
+    sample_timer: usize,
+    capacitors: (f32, f32),
+    samples: Vec<(f32, f32)>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            power: false,
+            registers: [0; 0x17],
+            frame_step: 0,
+            ch1: PulseChannel::new(true),
+            ch2: PulseChannel::new(false),
+            ch3: WaveChannel::new(),
+            ch4: NoiseChannel::new(),
+
+            sample_timer: 0,
+            capacitors: (0.0, 0.0),
+            samples: Vec::new(),
+        }
+    }
+
+    fn register(&self, addr: u16) -> u8 {
+        self.registers[(addr - NR10) as usize]
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            NR52 => {
+                let mut value = READ_MASKS[(NR52 - NR10) as usize];
+                if self.power {
+                    value.set_bit(7);
+                }
+                let channels = [
+                    self.ch1.enabled(),
+                    self.ch2.enabled(),
+                    self.ch3.enabled(),
+                    self.ch4.enabled(),
+                ];
+                for (bit, enabled) in channels.into_iter().enumerate() {
+                    if enabled {
+                        value.set_bit(bit as u8);
+                    }
+                }
+                value
+            }
+            0xFF10..=0xFF25 => self.register(addr) | READ_MASKS[(addr - NR10) as usize],
+            0xFF27..=0xFF2F => 0xFF,
+            0xFF30..=0xFF3F => self.ch3.read_ram(addr - 0xFF30),
+            _ => unreachable!("Address {addr:#06x} is not a sound register"),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            NR52 => {
+                let power = value.get_bit(7) == 1;
+                if self.power && !power {
+                    self.power_off();
+                } else if !self.power && power {
+                    // The frame sequencer restarts so its next step is 0
+                    self.frame_step = 0;
+                }
+                self.power = power;
+            }
+            0xFF27..=0xFF2F => (),
+            0xFF30..=0xFF3F => self.ch3.write_ram(addr - 0xFF30, value),
+            // Only the length counters can be written while powered off
+            _ if !self.power => match addr {
+                0xFF11 => self.ch1.load_length(value),
+                0xFF16 => self.ch2.load_length(value),
+                0xFF1B => self.ch3.load_length(value),
+                0xFF20 => self.ch4.load_length(value),
+                _ => (),
+            },
+            0xFF10..=0xFF25 => {
+                self.registers[(addr - NR10) as usize] = value;
+                // Length is clocked on even steps
+                let clocks_length = self.frame_step.is_multiple_of(2);
+                match addr {
+                    0xFF10..=0xFF14 => self.ch1.write(addr - 0xFF10, value, clocks_length),
+                    0xFF15..=0xFF19 => self.ch2.write(addr - 0xFF15, value, clocks_length),
+                    0xFF1A..=0xFF1E => self.ch3.write(addr - 0xFF1A, value, clocks_length),
+                    0xFF1F..=0xFF23 => self.ch4.write(addr - 0xFF1F, value, clocks_length),
+                    _ => (),
+                }
+            }
+            _ => unreachable!("Address {addr:#06x} is not a sound register"),
+        }
+    }
+
+    fn power_off(&mut self) {
+        self.registers = [0; 0x17];
+        self.ch1.power_off();
+        self.ch2.power_off();
+        self.ch3.power_off();
+        self.ch4.power_off();
+    }
+
+    /// Clocked on the falling edge of DIV bit 4, every 8192 T-cycles
+    pub fn step_frame_sequencer(&mut self) {
+        if !self.power {
+            return;
+        }
+
+        if self.frame_step.is_multiple_of(2) {
+            self.ch1.clock_length();
+            self.ch2.clock_length();
+            self.ch3.clock_length();
+            self.ch4.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.ch1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.ch1.clock_envelope();
+            self.ch2.clock_envelope();
+            self.ch4.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    /// Advances the channels by `cycles` T-cycles, producing a sample every `SAMPLE_PERIOD`
+    pub fn tick(&mut self, cycles: usize) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let step = remaining.min(SAMPLE_PERIOD - self.sample_timer);
+            if self.power {
+                self.ch1.tick(step as u32);
+                self.ch2.tick(step as u32);
+                self.ch3.tick(step as u32);
+                self.ch4.tick(step as u32);
+            }
+
+            remaining -= step;
+            self.sample_timer += step;
+            if self.sample_timer == SAMPLE_PERIOD {
+                self.sample_timer = 0;
+                let sample = self.mix();
+                if self.samples.len() < MAX_BUFFERED_SAMPLES {
+                    self.samples.push(sample);
+                }
+            }
+        }
+    }
+
+    // Converts a channel's digital output to the -1.0..=1.0 range, a disabled DAC outputs 0
+    fn dac(output: Option<u8>) -> f32 {
+        match output {
+            Some(value) => f32::from(value) / 7.5 - 1.0,
+            None => 0.0,
+        }
+    }
+
+    fn high_pass(capacitor: &mut f32, input: f32) -> f32 {
+        let output = input - *capacitor;
+        *capacitor = input - output * HIGH_PASS_CHARGE;
+        output
+    }
+
+    fn mix(&mut self) -> (f32, f32) {
+        if !self.power {
+            return (0.0, 0.0);
+        }
+
+        let outputs = [
+            Self::dac(self.ch1.output()),
+            Self::dac(self.ch2.output()),
+            Self::dac(self.ch3.output()),
+            Self::dac(self.ch4.output()),
+        ];
+
+        // NR51 routes channels to the right terminal in the lower nibble, left in the upper
+        let panning = self.register(NR51);
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (channel, output) in outputs.into_iter().enumerate() {
+            if panning.get_bit(channel as u8 + 4) == 1 {
+                left += output;
+            }
+            if panning.get_bit(channel as u8) == 1 {
+                right += output;
+            }
+        }
+
+        let volume = self.register(NR50);
+        let left_volume = f32::from(((volume >> 4) & 0x07) + 1) / 8.0;
+        let right_volume = f32::from((volume & 0x07) + 1) / 8.0;
+        let left = Self::high_pass(&mut self.capacitors.0, left / 4.0 * left_volume);
+        let right = Self::high_pass(&mut self.capacitors.1, right / 4.0 * right_volume);
+        (left, right)
+    }
+
+    /// Takes every sample produced since the last call, as stereo pairs at `APU_SAMPLE_RATE`
+    pub fn take_samples(&mut self) -> Vec<(f32, f32)> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_counter_disables_channel() {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        apu.write(0xFF12, 0xF0); // DAC on
+        apu.write(0xFF11, 0x3F); // length of 1
+        apu.write(0xFF14, 0xC0); // trigger with length enabled
+        assert_eq!(apu.read(NR52), 0xF1);
+
+        apu.step_frame_sequencer();
+        assert_eq!(apu.read(NR52), 0xF0);
+    }
+
+    #[test]
+    fn power_off_clears_registers() {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        apu.write(NR50, 0x77);
+        apu.write(0xFF30, 0x12);
+
+        apu.write(NR52, 0x00);
+        apu.write(NR51, 0xFF);
+        assert_eq!(apu.read(NR50), 0x00);
+        assert_eq!(apu.read(NR51), 0x00);
+        assert_eq!(apu.read(NR52), 0x70);
+        assert_eq!(apu.read(0xFF30), 0x12);
+    }
+    // Starts channel 3 with a 64 T-cycle sample period, wave RAM holding 0x00, 0x11, .. 0xFF
+    fn playing_wave_channel() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        for offset in 0..16 {
+            apu.write(0xFF30 + offset, offset as u8 * 0x11);
+        }
+        apu.write(0xFF1A, 0x80); // DAC on
+        apu.write(0xFF1D, 0xE0);
+        apu.write(0xFF1E, 0x87); // trigger, frequency 0x7E0
+        apu
+    }
+
+    #[test]
+    fn wave_ram_is_only_reachable_as_a_sample_is_read() {
+        let mut apu = playing_wave_channel();
+        assert_eq!(apu.read(0xFF30), 0xFF);
+
+        // The first sample is fetched 3 2MHz cycles late
+        apu.tick(64 + 6);
+        assert_eq!(apu.read(0xFF3F), 0x00);
+        apu.write(0xFF3F, 0xAB);
+
+        apu.tick(2);
+        assert_eq!(apu.read(0xFF30), 0xFF);
+        apu.write(0xFF31, 0xCD);
+
+        apu.write(0xFF1A, 0x00); // DAC off stops the channel
+        assert_eq!(apu.read(0xFF30), 0xAB);
+        assert_eq!(apu.read(0xFF31), 0x11);
+    }
+
+    #[test]
+    fn retrigger_as_sample_is_read_corrupts_wave_ram() {
+        let mut apu = playing_wave_channel();
+        // Up to sample 9, 2 T-cycles before sample 10 is read from byte 5
+        apu.tick(64 + 6 + 8 * 64 + 62);
+        apu.write(0xFF1E, 0x87);
+
+        apu.write(0xFF1A, 0x00);
+        let start: Vec<u8> = (0xFF30..0xFF38).map(|addr| apu.read(addr)).collect();
+        assert_eq!(start, [0x44, 0x55, 0x66, 0x77, 0x44, 0x55, 0x66, 0x77]);
+    }
+}
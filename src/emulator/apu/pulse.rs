@@ -0,0 +1,227 @@
+use super::{envelope::Envelope, length::LengthCounter};
+
+// 12.5%, 25%, 50% and 75% duty cycles, played from the lowest bit up
+const DUTY_PATTERNS: [u8; 4] = [0b1000_0000, 0b1000_0001, 0b1110_0001, 0b0111_1110];
+
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    // Set once a calculation has subtracted since the last trigger
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Self {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negate_used: false,
+        }
+    }
+
+    /// Updates the sweep from NR10, returns true if the channel should be disabled
+    fn write(&mut self, value: u8) -> bool {
+        let negate = value & 0x08 != 0;
+        let disable = self.negate && !negate && self.negate_used;
+        self.period = (value >> 4) & 0x07;
+        self.negate = negate;
+        self.shift = value & 0x07;
+        disable
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    // Returns None if the new frequency overflows past 2047
+    fn calculate(&mut self) -> Option<u16> {
+        let delta = self.shadow >> self.shift;
+        let frequency = if self.negate {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+        (frequency <= 2047).then_some(frequency)
+    }
+}
+
+pub struct PulseChannel {
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+    // Only channel 1 has a frequency sweep
+    sweep: Option<Sweep>,
+}
+
+impl PulseChannel {
+    pub fn new(with_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: with_sweep.then(Sweep::new),
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - u32::from(self.frequency)) * 4
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the channel's digital output, or None if its DAC is off
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.dac_enabled() {
+            return None;
+        }
+        let high = (DUTY_PATTERNS[self.duty as usize] >> self.duty_step) & 1 == 1;
+        Some(if self.enabled && high {
+            self.envelope.volume()
+        } else {
+            0
+        })
+    }
+
+    /// Handles a write to NRx0..=NRx4, `register` being the offset from NRx0
+    pub fn write(&mut self, register: u16, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut() {
+                    if sweep.write(value) {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | u16::from(value),
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (u16::from(value & 0x07) << 8);
+                let trigger = value & 0x80 != 0;
+                if self
+                    .length
+                    .write_control(value & 0x40 != 0, trigger, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("Pulse channels only have 5 registers"),
+        }
+    }
+
+    /// Length can still be written while the APU is off
+    pub fn load_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negate_used = false;
+            if sweep.shift != 0 && sweep.calculate().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.enabled {
+            return;
+        }
+        let mut remaining = cycles;
+        while remaining >= self.timer {
+            remaining -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= remaining;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+
+        if sweep.timer > 0 {
+            sweep.timer -= 1;
+        }
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        match sweep.calculate() {
+            None => self.enabled = false,
+            Some(frequency) if sweep.shift != 0 => {
+                sweep.shadow = frequency;
+                self.frequency = frequency;
+                // The new frequency is checked for overflow again but not written back
+                if sweep.calculate().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => (),
+        }
+    }
+
+    /// Clears every register except the length counter, which the DMG keeps while powered off
+    pub fn power_off(&mut self) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        length.power_off();
+        *self = Self {
+            length,
+            ..Self::new(self.sweep.is_some())
+        };
+    }
+}
@@ -0,0 +1,173 @@
+use super::length::LengthCounter;
+
+pub struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    volume_code: u8,
+    frequency: u16,
+    timer: u32,
+    // Index of the 4-bit sample being played, two samples per byte high nibble first
+    position: u8,
+    sample_buffer: u8,
+    // Whether a sample was fetched on the last cycle ticked, the only time the cpu can reach wave
+    // RAM while the channel plays
+    just_read: bool,
+    length: LengthCounter,
+    ram: [u8; 16],
+}
+
+impl WaveChannel {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            just_read: false,
+            length: LengthCounter::new(256),
+            ram: [0; 16],
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - u32::from(self.frequency)) * 2
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the channel's digital output, or None if its DAC is off
+    pub fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+        if !self.enabled {
+            return Some(0);
+        }
+        Some(match self.volume_code {
+            0 => 0,
+            code => self.sample_buffer >> (code - 1),
+        })
+    }
+
+    /// Handles a write to NR30..=NR34, `register` being the offset from NR30
+    pub fn write(&mut self, register: u16, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.volume_code = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | u16::from(value),
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (u16::from(value & 0x07) << 8);
+                let trigger = value & 0x80 != 0;
+                if self
+                    .length
+                    .write_control(value & 0x40 != 0, trigger, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("The wave channel only has 5 registers"),
+        }
+    }
+
+    /// Length can still be written while the APU is off
+    pub fn load_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    // While the channel is playing the cpu can only reach the byte currently being read, and on
+    // DMG only on the cycle the channel reads it
+    fn ram_index(&self, offset: u16) -> Option<usize> {
+        if !self.enabled {
+            Some(offset as usize)
+        } else if self.just_read {
+            Some(usize::from(self.position / 2))
+        } else {
+            None
+        }
+    }
+
+    pub fn read_ram(&self, offset: u16) -> u8 {
+        self.ram_index(offset).map_or(0xFF, |index| self.ram[index])
+    }
+
+    pub fn write_ram(&mut self, offset: u16, value: u8) {
+        if let Some(index) = self.ram_index(offset) {
+            self.ram[index] = value;
+        }
+    }
+
+    fn trigger(&mut self) {
+        // Retriggering on DMG just as the channel reads a sample corrupts the first bytes of wave
+        // RAM with the block being read
+        if self.enabled && self.timer <= 2 {
+            let offset = usize::from(self.position.div_ceil(2)) & 0x0F;
+            if offset < 4 {
+                self.ram[0] = self.ram[offset];
+            } else {
+                let block = offset & !3;
+                self.ram.copy_within(block..block + 4, 0);
+            }
+        }
+
+        self.enabled = self.dac_enabled;
+        self.position = 0;
+        self.just_read = false;
+        // Restarting doesn't refill the sample buffer, the first sample played is at position 1.
+        // The first fetch is delayed by 3 extra 2MHz cycles
+        self.timer = self.period() + 6;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.enabled {
+            return;
+        }
+        let mut remaining = cycles;
+        while remaining >= self.timer {
+            remaining -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[usize::from(self.position / 2)];
+            self.sample_buffer = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+            self.just_read = true;
+        }
+        if remaining > 0 {
+            self.timer -= remaining;
+            self.just_read = false;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Clears every register except the length counter and wave RAM, which survive power off
+    pub fn power_off(&mut self) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(256));
+        length.power_off();
+        *self = Self {
+            length,
+            ram: self.ram,
+            ..Self::new()
+        };
+    }
+}
@@ -0,0 +1,84 @@
+pub mod wav;
+
+use std::error::Error;
+
+use super::apu::APU_SAMPLE_RATE;
+
+/// Somewhere to send the APU's stereo output, samples arrive resampled to `sample_rate`
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn push_samples(&mut self, samples: &[(f32, f32)]) -> Result<(), Box<dyn Error>>;
+}
+
+// Linear interpolation between the APU's samples
+struct Resampler {
+    // APU samples per output sample
+    step: f64,
+    // Offset into the next batch, 0.0 being the last sample of the previous batch
+    position: f64,
+    last: (f32, f32),
+}
+
+impl Resampler {
+    fn new(output_rate: u32) -> Self {
+        Self {
+            step: f64::from(APU_SAMPLE_RATE) / f64::from(output_rate),
+            position: 0.0,
+            last: (0.0, 0.0),
+        }
+    }
+
+    fn process(&mut self, input: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        let Some(&next_last) = input.last() else {
+            return Vec::new();
+        };
+
+        let sample = |i: usize| if i == 0 { self.last } else { input[i - 1] };
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.position + 1.0 <= input.len() as f64 {
+            let i = self.position as usize;
+            let t = (self.position - i as f64) as f32;
+            let (a, b) = (sample(i), sample(i + 1));
+            output.push((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t));
+            self.position += self.step;
+        }
+
+        self.position -= input.len() as f64;
+        self.last = next_last;
+        output
+    }
+}
+
+/// Pairs a sink with the resampler feeding it
+pub struct AudioOutput {
+    sink: Box<dyn AudioSink>,
+    resampler: Resampler,
+}
+
+impl AudioOutput {
+    pub fn new(sink: Box<dyn AudioSink>) -> Self {
+        let resampler = Resampler::new(sink.sample_rate());
+        Self { sink, resampler }
+    }
+
+    pub fn push_samples(&mut self, samples: &[(f32, f32)]) -> Result<(), Box<dyn Error>> {
+        let resampled = self.resampler.process(samples);
+        if resampled.is_empty() {
+            return Ok(());
+        }
+        self.sink.push_samples(&resampled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resampler_keeps_rate_across_batches() {
+        let mut resampler = Resampler::new(48_000);
+        let batch = vec![(0.5, -0.5); APU_SAMPLE_RATE as usize / 64];
+        let produced: usize = (0..64).map(|_| resampler.process(&batch).len()).sum();
+        assert!(produced.abs_diff(48_000) <= 1);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::AudioSink;
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+/// Writes the stereo stream to a 16-bit PCM WAV file
+pub struct WavSink {
+    writer: BufWriter<File>,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl WavSink {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        let mut sink = Self {
+            writer: BufWriter::new(File::create(path)?),
+            sample_rate,
+            data_size: 0,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        w.write_all(b"WAVE")?;
+        w.write_all(b"fmt ")?;
+        w.write_all(&16u32.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?; // PCM
+        w.write_all(&CHANNELS.to_le_bytes())?;
+        w.write_all(&self.sample_rate.to_le_bytes())?;
+        w.write_all(&(self.sample_rate * u32::from(BLOCK_ALIGN)).to_le_bytes())?;
+        w.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+        w.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        w.write_all(b"data")?;
+        w.write_all(&self.data_size.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Rewrites the header with the final sizes, also done when the sink is dropped
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, samples: &[(f32, f32)]) -> Result<(), Box<dyn Error>> {
+        for (left, right) in samples {
+            for sample in [left, right] {
+                let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+                self.writer.write_all(&sample.to_le_bytes())?;
+            }
+        }
+        self.data_size += samples.len() as u32 * u32::from(BLOCK_ALIGN);
+        Ok(())
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            eprintln!("Unable to finish WAV file: {e}");
+        }
+    }
+}
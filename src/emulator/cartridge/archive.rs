@@ -0,0 +1,93 @@
+//! Unpacks roms kept in gzip or zip archives, recognised by their magic bytes rather than the
+//! file name so compressed roms can be loaded from memory too
+
+use std::io::{Cursor, Read};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use super::CartridgeError;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 0x03, 0x04];
+
+fn is_rom_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".gb") || name.ends_with(".gbc")
+}
+
+fn unzip(data: Vec<u8>) -> Result<Vec<u8>, CartridgeError> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(data)).map_err(|e| CartridgeError::Archive(e.to_string()))?;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| CartridgeError::Archive(e.to_string()))?;
+        if entry.is_file() && entry.name().is_ok_and(|name| is_rom_name(&name)) {
+            let mut rom = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut rom)?;
+            return Ok(rom);
+        }
+    }
+    Err(CartridgeError::NoRomInArchive)
+}
+
+/// Returns the rom inside `data` if it's an archive, otherwise `data` itself
+pub fn unpack(data: Vec<u8>) -> Result<Vec<u8>, CartridgeError> {
+    if data.starts_with(&GZIP_MAGIC) {
+        let mut rom = Vec::new();
+        GzDecoder::new(data.as_slice()).read_to_end(&mut rom)?;
+        Ok(rom)
+    } else if data.starts_with(&ZIP_MAGIC) {
+        unzip(data)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    #[test]
+    fn gzip_is_unpacked() {
+        let rom = vec![0x42; 0x8000];
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&rom).unwrap();
+
+        assert_eq!(unpack(encoder.finish().unwrap()).unwrap(), rom);
+    }
+
+    #[test]
+    fn zip_picks_first_rom_entry() {
+        let rom = vec![0x42; 0x8000];
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        writer.start_file("readme.txt", options).unwrap();
+        writer.write_all(b"not a rom").unwrap();
+        writer.start_file("Game.GBC", options).unwrap();
+        writer.write_all(&rom).unwrap();
+        writer.start_file("other.gb", options).unwrap();
+        writer.write_all(&[0; 0x8000]).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        assert_eq!(unpack(archive).unwrap(), rom);
+    }
+
+    #[test]
+    fn zip_without_rom_is_an_error() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        assert!(matches!(
+            unpack(archive),
+            Err(CartridgeError::NoRomInArchive)
+        ));
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+
+use super::{
+    licensee::{new_licensee_name, old_licensee_name},
+    CartridgeError, RAM_BANK_SIZE, ROM_BANK_SIZE,
+};
+
+pub const HEADER_END: usize = 0x150;
+
+// The boot rom compares this against 0x104..=0x133 and locks up if it differs
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// An old licensee of 0x33 means the publisher is in the new licensee code instead
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    None,
+    /// Runs on both, with extra features on a Game Boy Color
+    Enhanced,
+    /// Only runs on a Game Boy Color
+    Required,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/// Everything stored in the cartridge header at 0x100..=0x14F
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+    /// Usually a NOP then a jump to the real start of the game
+    pub entry_point: [u8; 4],
+    pub logo_valid: bool,
+    pub title: String,
+    /// Four character code only present on later carts
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub cartridge_type: u8,
+    pub old_licensee: u8,
+    pub new_licensee: Option<String>,
+    /// Rom size in bytes
+    pub rom_size: usize,
+    /// External ram size in bytes as declared, MBC2's built in ram isn't counted
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub header_checksum_valid: bool,
+    /// Nothing checks the global checksum, plenty of homebrew leaves it wrong
+    pub global_checksum: u16,
+    pub global_checksum_valid: bool,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooShort(rom.len()));
+        }
+
+        let cgb_support = match rom[0x143] {
+            0x80 => CgbSupport::Enhanced,
+            0xC0 => CgbSupport::Required,
+            _ => CgbSupport::None,
+        };
+
+        // Later carts shrink the title to make room for a manufacturer code, older ones use
+        // 0x143 as part of the title
+        let manufacturer_code = &rom[0x13F..=0x142];
+        let has_manufacturer_code = cgb_support != CgbSupport::None
+            && manufacturer_code
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        let title_bytes = match cgb_support {
+            _ if has_manufacturer_code => &rom[0x134..=0x13E],
+            CgbSupport::None => &rom[0x134..=0x143],
+            _ => &rom[0x134..=0x142],
+        };
+        // Titles are ASCII padded with zeroes, anything else is shown as replacement characters
+        let title = String::from_utf8_lossy(title_bytes)
+            .trim_end_matches('\0')
+            .to_string();
+
+        let old_licensee = rom[0x14B];
+        let new_licensee = (old_licensee == USE_NEW_LICENSEE)
+            .then(|| String::from_utf8_lossy(&rom[0x144..=0x145]).to_string());
+
+        let rom_size = match rom[0x148] {
+            0x00..=0x08 => (ROM_BANK_SIZE * 2) << rom[0x148],
+            0x52 => ROM_BANK_SIZE * 72,
+            0x53 => ROM_BANK_SIZE * 80,
+            0x54 => ROM_BANK_SIZE * 96,
+            code => return Err(CartridgeError::UnknownRomSize(code)),
+        };
+
+        // 0x01 was never used by a licensed game
+        let ram_size = match rom[0x149] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => RAM_BANK_SIZE,
+            0x03 => RAM_BANK_SIZE * 4,
+            0x04 => RAM_BANK_SIZE * 16,
+            0x05 => RAM_BANK_SIZE * 8,
+            code => return Err(CartridgeError::UnknownRamSize(code)),
+        };
+
+        let header_checksum = rom[0x14D];
+        let global_checksum = u16::from_be_bytes([rom[0x14E], rom[0x14F]]);
+
+        Ok(Self {
+            entry_point: rom[0x100..=0x103].try_into().unwrap(),
+            logo_valid: rom[0x104..=0x133] == NINTENDO_LOGO,
+            title,
+            manufacturer_code: has_manufacturer_code
+                .then(|| String::from_utf8_lossy(manufacturer_code).to_string()),
+            cgb_support,
+            sgb_support: rom[0x146] == 0x03,
+            cartridge_type: rom[0x147],
+            old_licensee,
+            new_licensee,
+            rom_size,
+            ram_size,
+            destination: if rom[0x14A] == 0x00 {
+                Destination::Japan
+            } else {
+                Destination::Overseas
+            },
+            version: rom[0x14C],
+            header_checksum,
+            header_checksum_valid: header_checksum == Self::compute_header_checksum(rom),
+            global_checksum,
+            global_checksum_valid: global_checksum == Self::compute_global_checksum(rom),
+        })
+    }
+
+    /// The checksum the boot rom computes over 0x134..=0x14C
+    pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1))
+    }
+
+    /// Sum of every byte in the rom except the checksum itself
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(addr, _)| !matches!(addr, 0x14E | 0x14F))
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(u16::from(*byte)))
+    }
+
+    /// The publisher's name, looked up from whichever licensee code the cart uses
+    pub fn licensee(&self) -> Option<&'static str> {
+        match &self.new_licensee {
+            Some(code) => new_licensee_name(code),
+            None => old_licensee_name(self.old_licensee),
+        }
+    }
+
+    /// Describes the cartridge hardware declared by the type byte at 0x147
+    pub fn cartridge_type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "Unknown",
+        }
+    }
+}
+
+fn validity(valid: bool) -> &'static str {
+    if valid {
+        "ok"
+    } else {
+        "bad"
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Title: {}", self.title)?;
+        if let Some(code) = &self.manufacturer_code {
+            writeln!(f, "Manufacturer: {code}")?;
+        }
+        let licensee_code = match &self.new_licensee {
+            Some(code) => code.clone(),
+            None => format!("{:02X}", self.old_licensee),
+        };
+        writeln!(
+            f,
+            "Licensee: {} ({licensee_code})",
+            self.licensee().unwrap_or("Unknown")
+        )?;
+        writeln!(
+            f,
+            "Type: {} ({:#04x})",
+            self.cartridge_type_name(),
+            self.cartridge_type
+        )?;
+        writeln!(f, "ROM size: {} KiB", self.rom_size / 1024)?;
+        writeln!(f, "RAM size: {} KiB", self.ram_size / 1024)?;
+        writeln!(f, "CGB: {:?}", self.cgb_support)?;
+        writeln!(f, "SGB: {}", self.sgb_support)?;
+        writeln!(f, "Destination: {:?}", self.destination)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(
+            f,
+            "Entry point: {:02X} {:02X} {:02X} {:02X}",
+            self.entry_point[0], self.entry_point[1], self.entry_point[2], self.entry_point[3]
+        )?;
+        writeln!(f, "Logo: {}", validity(self.logo_valid))?;
+        writeln!(
+            f,
+            "Header checksum: {:#04x} ({})",
+            self.header_checksum,
+            validity(self.header_checksum_valid)
+        )?;
+        write!(
+            f,
+            "Global checksum: {:#06x} ({})",
+            self.global_checksum,
+            validity(self.global_checksum_valid)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parses_tetris_header() {
+        let rom = fs::read("./roms/games/Tetris.gb").unwrap();
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.entry_point, [0x00, 0xC3, 0x50, 0x01]);
+        assert!(header.logo_valid);
+        assert_eq!(header.licensee(), Some("Nintendo"));
+        assert_eq!(header.rom_size, 0x8000);
+        assert_eq!(header.cgb_support, CgbSupport::None);
+        assert!(header.header_checksum_valid);
+        assert!(header.global_checksum_valid);
+    }
+}
@@ -0,0 +1,138 @@
+use super::{BankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+pub struct Mbc1 {
+    rom_banks: usize,
+    ram_enabled: bool,
+    // Low 5 bits of the rom bank, writing 0 selects 1
+    bank1: u8,
+    // Upper rom bank bits, or the ram bank in mode 1
+    bank2: u8,
+    // Mode 1 lets bank2 switch 0x0000..=0x3FFF and the ram bank
+    advanced_mode: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom_banks: usize) -> Self {
+        Self {
+            rom_banks,
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_mode: false,
+        }
+    }
+
+    // Banks past the end of the rom wrap around since the upper bank lines aren't connected
+    fn rom_bank(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x0000..=0x3FFF if self.advanced_mode => self.bank2 << 5,
+            0x0000..=0x3FFF => 0,
+            _ => (self.bank2 << 5) | self.bank1,
+        };
+        usize::from(bank) % self.rom_banks
+    }
+
+    fn ram_offset(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if !self.ram_enabled || ram.is_empty() {
+            return None;
+        }
+        let bank = if self.advanced_mode { self.bank2 } else { 0 };
+        let offset = usize::from(bank) * RAM_BANK_SIZE + usize::from(addr - 0xA000);
+        Some(offset % ram.len())
+    }
+}
+
+impl BankController for Mbc1 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let offset = self.rom_bank(addr) * ROM_BANK_SIZE + usize::from(addr & 0x3FFF);
+        rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.bank1 = (value & 0x1F).max(1),
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            0x6000..=0x7FFF => self.advanced_mode = value & 0x01 == 1,
+            _ => unreachable!("Address {addr:#06x} is not an MBC1 register"),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        self.ram_offset(ram, addr)
+            .map_or(0xFF, |offset| ram[offset])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match self.ram_offset(ram, addr) {
+            Some(offset) => {
+                ram[offset] = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every bank starts with its own number so reads show which bank is mapped
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+        (0..banks)
+            .flat_map(|bank| {
+                let mut data = vec![0; ROM_BANK_SIZE];
+                data[0] = bank as u8;
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn upper_bits_reach_past_bank_31() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mbc1::new(128);
+
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_register(0x4000, 0x02);
+        mbc.write_register(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x45);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x40);
+    }
+
+    #[test]
+    fn bank_numbers_are_masked_by_rom_size() {
+        let rom = numbered_rom(8);
+        let mut mbc = Mbc1::new(8);
+
+        mbc.write_register(0x2000, 0x1A);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 2);
+        // Bank 0x10 masks down to 0, the zero check only looks at the written value
+        mbc.write_register(0x2000, 0x10);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0);
+    }
+
+    #[test]
+    fn ram_needs_enabling_and_mode_1_to_switch_banks() {
+        let mut ram = vec![0; RAM_BANK_SIZE * 4];
+        let mut mbc = Mbc1::new(4);
+
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_register(0x0000, 0x0A);
+        mbc.write_register(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[0], 0x12);
+
+        mbc.write_register(0x6000, 0x01);
+        mbc.write_ram(&mut ram, 0xA000, 0x34);
+        assert_eq!(ram[RAM_BANK_SIZE * 2], 0x34);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x34);
+    }
+}
@@ -0,0 +1,96 @@
+use super::{BankController, ROM_BANK_SIZE};
+
+// 512 half bytes of ram built into the MBC itself
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+pub struct Mbc2 {
+    rom_banks: usize,
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom_banks: usize) -> Self {
+        Self {
+            rom_banks,
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+
+    // Only 9 address lines reach the ram, so it repeats across 0xA000..=0xBFFF
+    fn ram_offset(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if !self.ram_enabled || ram.is_empty() {
+            return None;
+        }
+        Some(usize::from(addr & 0x01FF) % ram.len())
+    }
+}
+
+impl BankController for Mbc2 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => usize::from(self.rom_bank) % self.rom_banks,
+        };
+        let offset = bank * ROM_BANK_SIZE + usize::from(addr & 0x3FFF);
+        rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    // Address bit 8 picks the register, ram enable when clear and rom bank when set
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x3FFF if addr & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => self.rom_bank = (value & 0x0F).max(1),
+            0x4000..=0x7FFF => (),
+            _ => unreachable!("Address {addr:#06x} is not an MBC2 register"),
+        }
+    }
+
+    // Only the low nibble is stored, the upper one reads back as 1s
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        self.ram_offset(ram, addr)
+            .map_or(0xFF, |offset| ram[offset] | 0xF0)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match self.ram_offset(ram, addr) {
+            Some(offset) => {
+                ram[offset] = value & 0x0F;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_bit_8_selects_register() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 16];
+        rom[ROM_BANK_SIZE * 5] = 0x42;
+        let mut mbc = Mbc2::new(16);
+
+        // Bit 8 clear only touches ram enable
+        mbc.write_register(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0);
+        mbc.write_register(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x42);
+    }
+
+    #[test]
+    fn ram_is_four_bits_and_echoes() {
+        let mut ram = vec![0; MBC2_RAM_SIZE];
+        let mut mbc = Mbc2::new(2);
+        mbc.write_register(0x0000, 0x0A);
+
+        mbc.write_ram(&mut ram, 0xA001, 0x3C);
+        assert_eq!(ram[1], 0x0C);
+        assert_eq!(mbc.read_ram(&ram, 0xA001), 0xFC);
+        assert_eq!(mbc.read_ram(&ram, 0xA201), 0xFC);
+        assert_eq!(mbc.read_ram(&ram, 0xBE01), 0xFC);
+    }
+}
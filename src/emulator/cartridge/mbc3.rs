@@ -0,0 +1,128 @@
+use super::{rtc::Rtc, BankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+pub struct Mbc3 {
+    rom_banks: usize,
+    // Enables both ram and the clock registers
+    ram_enabled: bool,
+    rom_bank: u8,
+    // 0x00..=0x03 selects a ram bank, 0x08..=0x0C a clock register
+    ram_select: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(rom_banks: usize, rtc: Option<Rtc>) -> Self {
+        Self {
+            rom_banks,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            rtc,
+        }
+    }
+
+    fn ram_offset(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if ram.is_empty() {
+            return None;
+        }
+        let offset = usize::from(self.ram_select) * RAM_BANK_SIZE + usize::from(addr - 0xA000);
+        Some(offset % ram.len())
+    }
+}
+
+impl BankController for Mbc3 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => usize::from(self.rom_bank) % self.rom_banks,
+        };
+        let offset = bank * ROM_BANK_SIZE + usize::from(addr & 0x3FFF);
+        rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_select = value & 0x0F,
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            }
+            _ => unreachable!("Address {addr:#06x} is not an MBC3 register"),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match (self.ram_select, self.rtc.as_ref()) {
+            (0x00..=0x03, _) => self
+                .ram_offset(ram, addr)
+                .map_or(0xFF, |offset| ram[offset]),
+            (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_select),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match self.ram_select {
+            0x00..=0x03 => match self.ram_offset(ram, addr) {
+                Some(offset) => {
+                    ram[offset] = value;
+                    true
+                }
+                None => false,
+            },
+            // The clock is saved alongside ram
+            0x08..=0x0C => match self.rtc.as_mut() {
+                Some(rtc) => {
+                    rtc.write(self.ram_select, value);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::CPU_FREQ;
+
+    #[test]
+    fn clock_registers_replace_ram() {
+        let mut ram = vec![0; RAM_BANK_SIZE * 4];
+        let mut mbc = Mbc3::new(4, Some(Rtc::new()));
+        mbc.write_register(0x0000, 0x0A);
+
+        mbc.write_register(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[RAM_BANK_SIZE * 3], 0x12);
+
+        mbc.write_register(0x4000, 0x09);
+        mbc.write_ram(&mut ram, 0xA000, 30);
+        mbc.tick(CPU_FREQ * 60);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 30);
+        mbc.write_register(0x6000, 0x00);
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 31);
+    }
+}
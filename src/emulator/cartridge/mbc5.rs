@@ -0,0 +1,119 @@
+use super::{BankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+pub struct Mbc5 {
+    rom_banks: usize,
+    ram_enabled: bool,
+    // 9 bits, unlike the older MBCs bank 0 can be mapped at 0x4000
+    rom_bank: u16,
+    ram_bank: u8,
+    // Rumble carts wire ram bank bit 3 to the motor instead
+    has_rumble: bool,
+    rumbling: bool,
+}
+
+impl Mbc5 {
+    pub fn new(rom_banks: usize, has_rumble: bool) -> Self {
+        Self {
+            rom_banks,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumbling: false,
+        }
+    }
+
+    fn ram_offset(&self, ram: &[u8], addr: u16) -> Option<usize> {
+        if !self.ram_enabled || ram.is_empty() {
+            return None;
+        }
+        let offset = usize::from(self.ram_bank) * RAM_BANK_SIZE + usize::from(addr - 0xA000);
+        Some(offset % ram.len())
+    }
+}
+
+impl BankController for Mbc5 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => usize::from(self.rom_bank) % self.rom_banks,
+        };
+        let offset = bank * ROM_BANK_SIZE + usize::from(addr & 0x3FFF);
+        rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | u16::from(value),
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (u16::from(value & 0x01) << 8);
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumbling = value & 0x08 != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            0x6000..=0x7FFF => (),
+            _ => unreachable!("Address {addr:#06x} is not an MBC5 register"),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        self.ram_offset(ram, addr)
+            .map_or(0xFF, |offset| ram[offset])
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match self.ram_offset(ram, addr) {
+            Some(offset) => {
+                ram[offset] = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn rumbling(&self) -> bool {
+        self.rumbling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_bank_is_split_across_two_registers() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 512];
+        rom[ROM_BANK_SIZE * 0x1A5] = 0x42;
+        let mut mbc = Mbc5::new(512, false);
+
+        mbc.write_register(0x2000, 0xA5);
+        mbc.write_register(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x42);
+
+        mbc.write_register(0x2000, 0x00);
+        mbc.write_register(0x3000, 0x00);
+        rom[0] = 0x24;
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x24);
+    }
+
+    #[test]
+    fn rumble_takes_ram_bank_bit_3() {
+        let mut ram = vec![0; RAM_BANK_SIZE * 16];
+        let mut mbc = Mbc5::new(2, true);
+        mbc.write_register(0x0000, 0x0A);
+
+        mbc.write_register(0x4000, 0x0B);
+        assert!(mbc.rumbling());
+        mbc.write_ram(&mut ram, 0xA000, 0x12);
+        assert_eq!(ram[RAM_BANK_SIZE * 3], 0x12);
+
+        mbc.write_register(0x4000, 0x03);
+        assert!(!mbc.rumbling());
+    }
+}
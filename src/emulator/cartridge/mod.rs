@@ -0,0 +1,553 @@
+mod archive;
+mod header;
+mod licensee;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod patch;
+mod rtc;
+mod save;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub use header::{CartridgeHeader, CgbSupport};
+use mbc1::Mbc1;
+use mbc2::{Mbc2, MBC2_RAM_SIZE};
+use mbc3::Mbc3;
+use mbc5::Mbc5;
+use rtc::Rtc;
+pub use rtc::RtcClock;
+
+pub use super::errors::CartridgeError;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub(super) enum Mbc {
+    MBC1,
+    MBC2,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    MMM01,
+    M161,
+    HuC1,
+    HuC3,
+}
+
+/// Maps the cpu's view of 0x0000..=0x7FFF and 0xA000..=0xBFFF onto the cartridge's rom and ram
+trait BankController {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8;
+    /// Handles a write to 0x0000..=0x7FFF, which selects banks instead of writing to rom
+    fn write_register(&mut self, addr: u16, value: u8);
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8;
+    /// Returns whether the write reached ram, disabled or missing ram drops it
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool;
+
+    /// Advances anything on the cartridge that runs off the system clock
+    fn tick(&mut self, _cycles: usize) {}
+
+    /// The cartridge's real time clock, if it has one
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    /// Whether a rumble cart's motor is currently on
+    fn rumbling(&self) -> bool {
+        false
+    }
+}
+
+// 32KiB of rom mapped directly, plus up to 8KiB of ram on some carts
+struct NoMbc;
+
+impl BankController for NoMbc {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom.get(addr as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn write_register(&mut self, _addr: u16, _value: u8) {}
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        ram.get(addr as usize - 0xA000).copied().unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match ram.get_mut(addr as usize - 0xA000) {
+            Some(byte) => {
+                *byte = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A file next to the rom sharing its name, `game.gb.gz` counts as `game` like `game.gb` does
+fn sibling_path(rom_path: &Path, extension: &str) -> PathBuf {
+    let is_gzip = rom_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"));
+    if is_gzip {
+        rom_path.with_extension("").with_extension(extension)
+    } else {
+        rom_path.with_extension(extension)
+    }
+}
+
+pub struct Cartridge {
+    // Cartridge header information
+    header: CartridgeHeader,
+    battery: bool,
+    timer: bool,
+
+    // catridge ram and rom
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    controller: Box<dyn BankController>,
+
+    // Where battery backed ram is persisted, None for carts without a battery
+    save_path: Option<PathBuf>,
+    // Set by ram writes so unchanged saves aren't rewritten
+    ram_dirty: bool,
+    // Clock registers as of the last save, the clock moving also needs saving
+    saved_rtc: Option<[u8; 5]>,
+    // Where the rom's cheats are kept, None for roms loaded from memory
+    cheats_path: Option<PathBuf>,
+}
+
+impl Cartridge {
+    /// Loads the rom at `rom_path`, applying any IPS, UPS or BPS patch sharing its name
+    pub fn from(rom_path: &str) -> Result<Self, CartridgeError> {
+        let patch_path = patch::find_patch(Path::new(rom_path));
+        Self::load(rom_path, patch_path.as_deref())
+    }
+
+    /// Loads the rom at `rom_path` with the patch at `patch_path` applied
+    pub fn from_patched(rom_path: &str, patch_path: &str) -> Result<Self, CartridgeError> {
+        Self::load(rom_path, Some(Path::new(patch_path)))
+    }
+
+    fn load(rom_path: &str, patch_path: Option<&Path>) -> Result<Self, CartridgeError> {
+        println!("Looking for rom at '{rom_path}'");
+        let mut rom = archive::unpack(fs::read(rom_path)?)?;
+        if let Some(patch_path) = patch_path {
+            println!("Applying patch '{}'", patch_path.display());
+            rom = patch::apply(&rom, &fs::read(patch_path)?)?;
+        }
+        let mut cartridge = Self::parse(rom)?;
+        cartridge.cheats_path = Some(sibling_path(Path::new(rom_path), "cht"));
+        let save_path = (cartridge.battery && (!cartridge.ram.is_empty() || cartridge.timer))
+            .then(|| save::save_path(Path::new(rom_path)));
+        cartridge.with_save_path(save_path)
+    }
+
+    /// Loads a rom held in memory, gzip and zip archives are unpacked first. Nothing is saved
+    /// until a save path is given with `with_save_path`
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CartridgeError> {
+        Self::parse(archive::unpack(bytes.to_vec())?)
+    }
+
+    /// Like `from_bytes`, reading the whole rom or archive from `reader`
+    #[cfg(test)]
+    pub fn from_reader(mut reader: impl std::io::Read) -> Result<Self, CartridgeError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::parse(archive::unpack(bytes)?)
+    }
+
+    fn parse(mut raw_file: Vec<u8>) -> Result<Self, CartridgeError> {
+        let header = CartridgeHeader::parse(&raw_file)?;
+        // The boot rom refuses to start a cartridge whose header checksum is wrong
+        if !header.header_checksum_valid {
+            return Err(CartridgeError::HeaderChecksum {
+                expected: header.header_checksum,
+                actual: CartridgeHeader::compute_header_checksum(&raw_file),
+            });
+        }
+
+        println!("Found Rom: {}", header.title);
+
+        let (mbc, has_ram, battery, timer) = match header.cartridge_type {
+            0x00 => (None, false, false, false),
+            0x01 => (Some(Mbc::MBC1), false, false, false),
+            0x02 => (Some(Mbc::MBC1), true, false, false),
+            0x03 => (Some(Mbc::MBC1), true, true, false),
+            0x05 => (Some(Mbc::MBC2), false, false, false),
+            0x06 => (Some(Mbc::MBC2), false, true, false),
+            0x08 => (None, true, false, false),
+            0x09 => (None, true, true, false),
+            0x0b => (Some(Mbc::MMM01), false, false, false),
+            0x0c => (Some(Mbc::MMM01), true, false, false),
+            0x0d => (Some(Mbc::MMM01), true, true, false),
+            0x0f => (Some(Mbc::MBC3), false, true, true),
+            0x10 => (Some(Mbc::MBC3), true, true, true),
+            0x11 => (Some(Mbc::MBC3), false, false, false),
+            0x12 => (Some(Mbc::MBC3), true, false, false),
+            0x13 => (Some(Mbc::MBC3), true, true, false),
+            0x19 => (Some(Mbc::MBC5), false, false, false),
+            0x1a => (Some(Mbc::MBC5), true, false, false),
+            0x1b => (Some(Mbc::MBC5), true, true, false),
+            0x1c => (Some(Mbc::MBC5), false, false, false),
+            0x1d => (Some(Mbc::MBC5), true, false, false),
+            0x1e => (Some(Mbc::MBC5), true, true, false),
+            0x20 => (Some(Mbc::MBC6), false, false, false),
+            0xfe => (Some(Mbc::HuC3), false, false, false),
+            0xff => (Some(Mbc::HuC1), true, true, false),
+            code => return Err(CartridgeError::UnknownType(code)),
+        };
+
+        if raw_file.len() < header.rom_size {
+            return Err(CartridgeError::SizeMismatch {
+                expected: header.rom_size,
+                actual: raw_file.len(),
+            });
+        }
+        // Overdumps pad the rom with junk past what the header declares, nothing maps it
+        if raw_file.len() > header.rom_size {
+            eprintln!(
+                "Rom is {} bytes but the header declares {}, ignoring the extra bytes",
+                raw_file.len(),
+                header.rom_size
+            );
+            raw_file.truncate(header.rom_size);
+        }
+        let rom_banks = header.rom_size / ROM_BANK_SIZE;
+
+        let ram_size = match header.ram_size {
+            // MBC2 has its own ram and declares a size of 0
+            _ if matches!(mbc, Some(Mbc::MBC2)) => MBC2_RAM_SIZE,
+            _ if !has_ram => 0,
+            // Some homebrew and test roms declare ram without a size, give them one bank
+            0 => RAM_BANK_SIZE,
+            size => size,
+        };
+
+        let controller: Box<dyn BankController> = match mbc {
+            None => Box::new(NoMbc),
+            Some(Mbc::MBC1) => Box::new(Mbc1::new(rom_banks)),
+            Some(Mbc::MBC2) => Box::new(Mbc2::new(rom_banks)),
+            Some(Mbc::MBC3) => Box::new(Mbc3::new(rom_banks, timer.then(Rtc::new))),
+            Some(Mbc::MBC5) => {
+                let has_rumble = matches!(header.cartridge_type, 0x1c..=0x1e);
+                Box::new(Mbc5::new(rom_banks, has_rumble))
+            }
+            _ => return Err(CartridgeError::UnsupportedMbc(header.cartridge_type)),
+        };
+
+        Ok(Self {
+            header,
+            battery,
+            timer,
+
+            rom: raw_file,
+            ram: vec![0xFF; ram_size],
+            controller,
+
+            save_path: None,
+            ram_dirty: false,
+            saved_rtc: None,
+            cheats_path: None,
+        })
+    }
+
+    /// Moves where battery backed ram and the clock are saved, loading any save already there.
+    /// None stops the cartridge from saving at all
+    pub fn with_save_path(mut self, path: Option<PathBuf>) -> Result<Self, CartridgeError> {
+        if let Some(path) = &path {
+            save::load(path, &mut self.ram, self.controller.rtc())?;
+            println!("Using save file '{}'", path.display());
+        }
+        self.save_path = path;
+        self.ram_dirty = false;
+        self.saved_rtc = self.controller.rtc().map(|rtc| rtc.snapshot());
+        Ok(self)
+    }
+
+    /// Chooses what drives the real time clock on carts that have one, emulated by default
+    pub fn with_rtc_clock(mut self, clock: RtcClock) -> Self {
+        if let Some(rtc) = self.controller.rtc() {
+            rtc.set_clock(clock);
+        }
+        self
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    pub fn title(&self) -> String {
+        self.header.title.clone()
+    }
+
+    /// The whole rom as loaded, every bank included
+    #[cfg(test)]
+    pub fn bytes(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn gb_compatible(&self) -> bool {
+        self.header.cgb_support != CgbSupport::Required
+    }
+
+    /// Reads from rom through the currently selected banks
+    pub fn read(&self, addr: u16) -> u8 {
+        self.controller.read_rom(&self.rom, addr)
+    }
+
+    /// Writes to rom are picked up by the MBC as bank and ram control
+    pub fn write(&mut self, addr: u16, value: u8) {
+        self.controller.write_register(addr, value);
+    }
+
+    /// Reads external ram at 0xA000..=0xBFFF, disabled or missing ram reads as 0xFF
+    pub fn read_ram(&self, addr: u16) -> u8 {
+        self.controller.read_ram(&self.ram, addr)
+    }
+
+    pub fn write_ram(&mut self, addr: u16, value: u8) {
+        if self.controller.write_ram(&mut self.ram, addr, value) {
+            self.ram_dirty = true;
+        }
+    }
+
+    /// Writes a GameShark code to `addr`, straight into ram `bank` whatever bank is selected and
+    /// whether or not ram is enabled, or like a cpu write without a bank. Cheats are reapplied
+    /// every frame and aren't part of the game's save, so ram isn't marked dirty
+    pub fn write_cheat(&mut self, bank: Option<u8>, addr: u16, value: u8) {
+        let Some(bank) = bank else {
+            self.controller.write_ram(&mut self.ram, addr, value);
+            return;
+        };
+        if self.ram.is_empty() {
+            return;
+        }
+        let offset = usize::from(bank) * RAM_BANK_SIZE + (addr as usize - 0xA000);
+        let len = self.ram.len();
+        self.ram[offset % len] = value;
+    }
+
+    /// `<rom name>.cht` next to the rom
+    pub fn cheats_path(&self) -> Option<&Path> {
+        self.cheats_path.as_deref()
+    }
+
+    /// Writes battery backed ram and the clock to the save file if either changed since the last
+    /// save
+    pub fn save(&mut self) -> Result<(), std::io::Error> {
+        let Some(path) = &self.save_path else {
+            return Ok(());
+        };
+        let rtc = self.controller.rtc().map(|rtc| rtc.snapshot());
+        if !self.ram_dirty && rtc == self.saved_rtc {
+            return Ok(());
+        }
+
+        save::write(path, &self.ram, self.controller.rtc())?;
+        self.ram_dirty = false;
+        self.saved_rtc = rtc;
+        Ok(())
+    }
+
+    pub fn tick(&mut self, cycles: usize) {
+        self.controller.tick(cycles);
+    }
+
+    pub fn rumbling(&self) -> bool {
+        self.controller.rumbling()
+    }
+}
+
+// Flushes the save when the rom is switched or the emulator closes
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            eprintln!("Unable to write save file: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::CPU_FREQ;
+    use std::env;
+
+    // A 32KiB rom with 8KiB of battery backed ram
+    fn battery_rom_of_type(cartridge_type: u8) -> Vec<u8> {
+        let mut rom = vec![0; ROM_BANK_SIZE * 2];
+        rom[0x147] = cartridge_type;
+        rom[0x148] = 0x00;
+        rom[0x149] = 0x02;
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+        rom
+    }
+
+    fn battery_rom() -> Vec<u8> {
+        battery_rom_of_type(0x03)
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let error = |rom: Vec<u8>| Cartridge::parse(rom).err().unwrap();
+
+        assert!(matches!(
+            error(vec![0; 0x100]),
+            CartridgeError::TooShort(0x100)
+        ));
+
+        let mut rom = battery_rom();
+        rom[0x14D] ^= 0xFF;
+        assert!(matches!(error(rom), CartridgeError::HeaderChecksum { .. }));
+
+        let mut rom = battery_rom();
+        rom[0x147] = 0x04;
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+        assert!(matches!(error(rom), CartridgeError::UnknownType(0x04)));
+
+        let mut rom = battery_rom();
+        rom[0x147] = 0xFF;
+        rom[0x14D] = CartridgeHeader::compute_header_checksum(&rom);
+        assert!(matches!(error(rom), CartridgeError::UnsupportedMbc(0xFF)));
+
+        let mut rom = battery_rom();
+        rom.truncate(ROM_BANK_SIZE + 0x100);
+        assert!(matches!(
+            error(rom),
+            CartridgeError::SizeMismatch {
+                expected: 0x8000,
+                actual: 0x4100
+            }
+        ));
+    }
+
+    #[test]
+    fn roms_load_from_readers() {
+        let rom = battery_rom();
+        let cartridge = Cartridge::from_reader(rom.as_slice()).unwrap();
+        assert_eq!(cartridge.bytes(), rom);
+        assert!(cartridge.cheats_path().is_none());
+    }
+
+    #[test]
+    fn overdumped_roms_are_truncated() {
+        let mut rom = battery_rom();
+        rom.resize(ROM_BANK_SIZE * 4, 0xFF);
+        let cartridge = Cartridge::parse(rom).unwrap();
+        assert_eq!(cartridge.bytes().len(), ROM_BANK_SIZE * 2);
+    }
+
+    #[test]
+    fn dropped_ram_writes_leave_ram_clean() {
+        let mut cartridge = Cartridge::parse(battery_rom()).unwrap();
+        cartridge.write_ram(0xA010, 0x42);
+        assert!(!cartridge.ram_dirty);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write_ram(0xA010, 0x42);
+        assert!(cartridge.ram_dirty);
+    }
+
+    #[test]
+    fn cheats_leave_ram_clean() {
+        let mut cartridge = Cartridge::parse(battery_rom()).unwrap();
+        cartridge.write_cheat(Some(0), 0xA010, 0x42);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write_cheat(None, 0xA011, 0x43);
+        assert_eq!(cartridge.read_ram(0xA010), 0x42);
+        assert_eq!(cartridge.read_ram(0xA011), 0x43);
+        assert!(!cartridge.ram_dirty);
+    }
+
+    #[test]
+    fn battery_ram_round_trips_through_save_file() {
+        let rom_path = env::temp_dir().join("gameboy_emulator_battery_test.gb");
+        let save_path = rom_path.with_extension("sav");
+        fs::write(&rom_path, battery_rom()).unwrap();
+        _ = fs::remove_file(&save_path);
+
+        let mut cartridge = Cartridge::from(rom_path.to_str().unwrap()).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write_ram(0xA010, 0x42);
+        drop(cartridge);
+        assert_eq!(fs::read(&save_path).unwrap()[0x10], 0x42);
+
+        let mut cartridge = Cartridge::from(rom_path.to_str().unwrap()).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        assert_eq!(cartridge.read_ram(0xA010), 0x42);
+
+        fs::remove_file(rom_path).unwrap();
+        fs::remove_file(save_path).unwrap();
+    }
+
+    #[test]
+    fn clock_catches_up_on_time_since_save() {
+        let rom_path = env::temp_dir().join("gameboy_emulator_rtc_test.gb");
+        let save_path = rom_path.with_extension("sav");
+        // MBC3 with a clock, ram and a battery
+        fs::write(&rom_path, battery_rom_of_type(0x10)).unwrap();
+        _ = fs::remove_file(&save_path);
+
+        let mut cartridge = Cartridge::from(rom_path.to_str().unwrap()).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x0A);
+        cartridge.write_ram(0xA000, 5);
+        drop(cartridge);
+
+        // Pretend the save was written an hour and a half ago
+        let mut data = fs::read(&save_path).unwrap();
+        assert_eq!(data.len(), RAM_BANK_SIZE + 48);
+        let timestamp = u64::from_le_bytes(data[RAM_BANK_SIZE + 40..].try_into().unwrap());
+        data[RAM_BANK_SIZE + 40..].copy_from_slice(&(timestamp - 5400).to_le_bytes());
+        fs::write(&save_path, data).unwrap();
+
+        let mut cartridge = Cartridge::from(rom_path.to_str().unwrap()).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        cartridge.write(0x4000, 0x09);
+        assert_eq!(cartridge.read_ram(0xA000), 30);
+        cartridge.write(0x4000, 0x0A);
+        assert_eq!(cartridge.read_ram(0xA000), 6);
+
+        drop(cartridge);
+        fs::remove_file(rom_path).unwrap();
+        fs::remove_file(save_path).unwrap();
+    }
+
+    #[test]
+    fn emulated_clock_is_saved_without_ram_writes() {
+        let rom_path = env::temp_dir().join("gameboy_emulator_emulated_rtc_test.gb");
+        let save_path = rom_path.with_extension("sav");
+        // MBC3 with a clock and a battery but no ram
+        fs::write(&rom_path, battery_rom_of_type(0x0F)).unwrap();
+        _ = fs::remove_file(&save_path);
+
+        let mut cartridge = Cartridge::from(rom_path.to_str().unwrap()).unwrap();
+        cartridge.tick(CPU_FREQ * 90);
+        cartridge.save().unwrap();
+        drop(cartridge);
+
+        let mut cartridge = Cartridge::from(rom_path.to_str().unwrap()).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        cartridge.write(0x4000, 0x09);
+        assert_eq!(cartridge.read_ram(0xA000), 1);
+        cartridge.write(0x4000, 0x08);
+        // The wall clock may tick over between saving and loading
+        assert!((30..=31).contains(&cartridge.read_ram(0xA000)));
+
+        drop(cartridge);
+        fs::remove_file(rom_path).unwrap();
+        fs::remove_file(save_path).unwrap();
+    }
+}
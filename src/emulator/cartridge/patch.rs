@@ -0,0 +1,353 @@
+//! Soft patching with IPS, UPS and BPS files, applied to the rom before it's parsed
+//!
+//! UPS and BPS patches end with the CRC32s of the rom they expect, the rom they produce and the
+//! patch itself, all three are checked
+
+use std::path::{Path, PathBuf};
+
+use super::{sibling_path, CartridgeError};
+
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+// Source, target and patch CRC32s
+const FOOTER_SIZE: usize = 12;
+
+/// A patch with the rom's name next to it, `game.gb` picks up `game.ips`, `game.ups` or `game.bps`
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| sibling_path(rom_path, ext))
+        .find(|path| path.is_file())
+}
+
+/// Applies whichever patch format `patch` is, worked out from its magic bytes
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    if let Some(records) = patch.strip_prefix(b"PATCH") {
+        apply_ips(rom, records)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(malformed("unrecognised patch format"))
+    }
+}
+
+fn malformed(reason: &str) -> CartridgeError {
+    CartridgeError::BadPatch(reason.to_string())
+}
+
+// Reads through a patch, running off the end is an error rather than a panic
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CartridgeError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| malformed("patch ends early"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, CartridgeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, len: usize) -> Result<usize, CartridgeError> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |value, byte| (value << 8) | usize::from(*byte)))
+    }
+
+    // UPS and BPS numbers, 7 bits at a time with the top bit marking the last byte
+    fn varint(&mut self) -> Result<usize, CartridgeError> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.u8()?;
+            value = value
+                .checked_add(usize::from(byte & 0x7F) * shift)
+                .ok_or_else(|| malformed("number too large"))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(128)
+                .ok_or_else(|| malformed("number too large"))?;
+            value += shift;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader::new(records);
+    loop {
+        if reader.bytes(3)? == b"EOF" {
+            break;
+        }
+        reader.pos -= 3;
+        let offset = reader.big_endian(3)?;
+        let size = reader.big_endian(2)?;
+
+        // A size of 0 marks a run of one repeated byte
+        let data = if size == 0 {
+            let count = reader.big_endian(2)?;
+            vec![reader.u8()?; count]
+        } else {
+            reader.bytes(size)?.to_vec()
+        };
+        if target.len() < offset + data.len() {
+            target.resize(offset + data.len(), 0);
+        }
+        target[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // Some patches follow EOF with the size to truncate the rom to
+    if let Ok(size) = reader.big_endian(3) {
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+fn crc_at(footer: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap())
+}
+
+// Checks the patch and source CRC32s, returning the body and the expected target CRC32
+fn verify_footer<'a>(rom: &[u8], patch: &'a [u8]) -> Result<(&'a [u8], u32), CartridgeError> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err(malformed("patch is too short"));
+    }
+    let (body, footer) = patch.split_at(patch.len() - FOOTER_SIZE);
+
+    let checks = [
+        (
+            "patch",
+            crc_at(footer, 2),
+            crc32fast::hash(&patch[..patch.len() - 4]),
+        ),
+        ("source rom", crc_at(footer, 0), crc32fast::hash(rom)),
+    ];
+    for (part, expected, actual) in checks {
+        if expected != actual {
+            return Err(CartridgeError::PatchChecksum {
+                part,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok((body, crc_at(footer, 1)))
+}
+
+fn verify_target(target: &[u8], expected: u32) -> Result<(), CartridgeError> {
+    let actual = crc32fast::hash(target);
+    if actual != expected {
+        return Err(CartridgeError::PatchChecksum {
+            part: "patched rom",
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let (body, target_crc) = verify_footer(rom, patch)?;
+    let mut reader = PatchReader::new(&body[4..]);
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    // Each hunk skips ahead then XORs bytes in until a zero
+    let mut offset = 0;
+    while reader.pos < reader.data.len() {
+        offset += reader.varint()?;
+        loop {
+            let xor = reader.u8()?;
+            if xor == 0 {
+                offset += 1;
+                break;
+            }
+            let byte = target
+                .get_mut(offset)
+                .ok_or_else(|| malformed("hunk writes past the end of the rom"))?;
+            *byte ^= xor;
+            offset += 1;
+        }
+    }
+
+    verify_target(&target, target_crc)?;
+    Ok(target)
+}
+
+// Copy offsets are relative to the last copy, the low bit is the sign
+fn signed_offset(offset: usize, data: usize) -> Result<usize, CartridgeError> {
+    let delta = data >> 1;
+    let offset = if data & 1 == 0 {
+        offset.checked_add(delta)
+    } else {
+        offset.checked_sub(delta)
+    };
+    offset.ok_or_else(|| malformed("copy offset out of range"))
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, CartridgeError> {
+    let (body, target_crc) = verify_footer(rom, patch)?;
+    let mut reader = PatchReader::new(&body[4..]);
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    let mut target = Vec::new();
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while reader.pos < reader.data.len() {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        match action & 3 {
+            // Source read, the bytes at the same position in the original rom
+            0 => {
+                let start = target.len();
+                let bytes = rom
+                    .get(start..start + len)
+                    .ok_or_else(|| malformed("source read past the end of the rom"))?;
+                target.extend_from_slice(bytes);
+            }
+            // Target read, bytes stored in the patch
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // Source copy, from anywhere in the original rom
+            2 => {
+                source_offset = signed_offset(source_offset, reader.varint()?)?;
+                let bytes = rom
+                    .get(source_offset..source_offset + len)
+                    .ok_or_else(|| malformed("source copy past the end of the rom"))?;
+                target.extend_from_slice(bytes);
+                source_offset += len;
+            }
+            // Target copy, from what's been written so far and may overlap itself
+            _ => {
+                target_offset = signed_offset(target_offset, reader.varint()?)?;
+                for _ in 0..len {
+                    let byte = *target
+                        .get(target_offset)
+                        .ok_or_else(|| malformed("target copy past the written rom"))?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(malformed("patched rom is the wrong size"));
+    }
+    verify_target(&target, target_crc)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32fast::hash(source).to_le_bytes());
+        patch.extend(crc32fast::hash(target).to_le_bytes());
+        patch.extend(crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips_writes_records_and_runs() {
+        let rom = vec![0; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend([0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend(b"EOF");
+
+        let patched = apply(&rom, &patch).unwrap();
+        assert_eq!(patched, [0, 0xAA, 0xBB, 0, 0, 0, 0, 0, 0xCC, 0xCC, 0xCC]);
+    }
+
+    #[test]
+    fn ups_xors_hunks_and_checks_crcs() {
+        let rom = vec![1, 2, 3, 4];
+        let target = vec![1, 7, 3, 4, 5];
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(4));
+        patch.extend(varint(5));
+        patch.extend(varint(1));
+        patch.extend([2 ^ 7, 0x00]);
+        // The terminating zero also moves past a byte
+        patch.extend(varint(1));
+        patch.extend([5, 0x00]);
+        let patch = with_footer(patch, &rom, &target);
+
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+        assert!(matches!(
+            apply(&[9, 9, 9, 9], &patch),
+            Err(CartridgeError::PatchChecksum {
+                part: "source rom",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn bps_runs_every_action() {
+        let rom = vec![10, 20, 30, 40];
+        let target = vec![10, 20, 99, 30, 40, 40, 40];
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(rom.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // Source read 2
+        patch.extend(varint(1 << 2));
+        // Target read 1
+        patch.extend(varint(1));
+        patch.push(99);
+        // Source copy 2 from offset 2
+        patch.extend(varint((1 << 2) | 2));
+        patch.extend(varint(2 << 1));
+        // Target copy 2 from offset 4, reading the byte it just wrote
+        patch.extend(varint((1 << 2) | 3));
+        patch.extend(varint(4 << 1));
+
+        let good = with_footer(patch.clone(), &rom, &target);
+        assert_eq!(apply(&rom, &good).unwrap(), target);
+
+        let bad = with_footer(patch, &rom, &[0; 7]);
+        assert!(matches!(
+            apply(&rom, &bad),
+            Err(CartridgeError::PatchChecksum {
+                part: "patched rom",
+                ..
+            })
+        ));
+    }
+}
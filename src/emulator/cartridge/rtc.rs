@@ -0,0 +1,267 @@
+use std::time::{Duration, Instant};
+
+use crate::emulator::CPU_FREQ;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// The day counter is 9 bits, overflowing it sets the carry flag
+const DAYS: u64 = 512;
+
+/// What drives the cartridge clock forward
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcClock {
+    /// Counts emulated cycles, the clock stops while the emulator is paused
+    Emulated,
+    /// Follows the host clock, like a real cartridge's crystal
+    WallClock,
+}
+
+/// The MBC3 real time clock
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+    // Snapshot of the registers the cpu reads, taken on a 0x00 then 0x01 latch write
+    latched: [u8; 5],
+    latch_armed: bool,
+
+    clock: RtcClock,
+    // Cycles counted towards the next second while emulated
+    cycles: usize,
+    // Host time already accounted for while on the wall clock
+    last_sync: Instant,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+            latched: [0; 5],
+            latch_armed: false,
+
+            clock: RtcClock::Emulated,
+            cycles: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        self.sync();
+        self.clock = clock;
+        self.cycles = 0;
+        self.last_sync = Instant::now();
+    }
+
+    /// The running clock's registers in 0x08..=0x0C order, brought up to date first
+    pub fn snapshot(&mut self) -> [u8; 5] {
+        self.sync();
+        self.registers()
+    }
+
+    pub fn latched(&self) -> [u8; 5] {
+        self.latched
+    }
+
+    /// Puts back registers saved by `snapshot` and `latched`
+    pub fn restore(&mut self, registers: [u8; 5], latched: [u8; 5]) {
+        self.seconds = registers[0] & 0x3F;
+        self.minutes = registers[1] & 0x3F;
+        self.hours = registers[2] & 0x1F;
+        self.days = u16::from(registers[3]) | (u16::from(registers[4] & 0x01) << 8);
+        self.halted = registers[4] & 0x40 != 0;
+        self.day_carry = registers[4] & 0x80 != 0;
+        self.latched = latched;
+        self.cycles = 0;
+        self.last_sync = Instant::now();
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        let day_high = (self.days >> 8) as u8
+            | if self.halted { 0x40 } else { 0 }
+            | if self.day_carry { 0x80 } else { 0 };
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            day_high,
+        ]
+    }
+
+    /// Reads one of the latched registers selected by 0x08..=0x0C
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[usize::from(register - 0x08)]
+    }
+
+    /// Writes go straight to the running clock, unused bits are dropped
+    pub fn write(&mut self, register: u8, value: u8) {
+        let index = usize::from(register - 0x08);
+        self.sync();
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                // Writing the seconds resets the sub-second divider
+                self.cycles = 0;
+                self.last_sync = Instant::now();
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | u16::from(value),
+            0x0C => {
+                self.days = (self.days & 0xFF) | (u16::from(value & 0x01) << 8);
+                self.halted = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+            _ => unreachable!("{register:#04x} is not an RTC register"),
+        }
+        self.latched[index] = self.registers()[index];
+    }
+
+    /// Handles a write to 0x6000..=0x7FFF, writing 0x00 then 0x01 latches the clock
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.sync();
+            self.latched = self.registers();
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    pub fn tick(&mut self, cycles: usize) {
+        if self.clock != RtcClock::Emulated || self.halted {
+            return;
+        }
+        self.cycles += cycles;
+        if self.cycles >= CPU_FREQ {
+            self.advance((self.cycles / CPU_FREQ) as u64);
+            self.cycles %= CPU_FREQ;
+        }
+    }
+
+    // Catches up with the host clock, keeping any partial second for next time
+    fn sync(&mut self) {
+        if self.clock != RtcClock::WallClock {
+            return;
+        }
+        let elapsed = self.last_sync.elapsed().as_secs();
+        if self.halted {
+            self.last_sync = Instant::now();
+            return;
+        }
+        self.last_sync += Duration::from_secs(elapsed);
+        self.advance(elapsed);
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    // Out of range values count up to their bit width before wrapping, without carrying
+    fn step_second(&mut self) {
+        if self.seconds != 59 {
+            self.seconds = (self.seconds + 1) & 0x3F;
+            return;
+        }
+        self.seconds = 0;
+        if self.minutes != 59 {
+            self.minutes = (self.minutes + 1) & 0x3F;
+            return;
+        }
+        self.minutes = 0;
+        if self.hours != 23 {
+            self.hours = (self.hours + 1) & 0x1F;
+            return;
+        }
+        self.hours = 0;
+        self.days = (self.days + 1) % DAYS as u16;
+        if self.days == 0 {
+            self.day_carry = true;
+        }
+    }
+
+    /// Moves the clock forward, used for both ticking and catching up on elapsed time
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halted {
+            return;
+        }
+        while seconds > 0 && !self.in_range() {
+            self.step_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let total = u64::from(self.seconds)
+            + u64::from(self.minutes) * 60
+            + u64::from(self.hours) * 3600
+            + u64::from(self.days) * SECONDS_PER_DAY
+            + seconds;
+        let days = total / SECONDS_PER_DAY;
+        if days >= DAYS {
+            self.day_carry = true;
+        }
+        self.days = (days % DAYS) as u16;
+        self.hours = (total % SECONDS_PER_DAY / 3600) as u8;
+        self.minutes = (total % 3600 / 60) as u8;
+        self.seconds = (total % 60) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|register| rtc.read(register))
+    }
+
+    #[test]
+    fn reads_only_change_on_latch() {
+        let mut rtc = Rtc::new();
+        rtc.tick(CPU_FREQ * 61);
+        assert_eq!(rtc.read(0x08), 0);
+        assert_eq!(latched(&mut rtc), [1, 1, 0, 0, 0]);
+
+        // Writing 0x01 without 0x00 first doesn't latch
+        rtc.tick(CPU_FREQ);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 1);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let mut rtc = Rtc::new();
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+        rtc.write(0x0A, 23);
+        rtc.write(0x09, 59);
+        rtc.write(0x08, 59);
+        rtc.advance(1);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut rtc = Rtc::new();
+        rtc.write(0x0C, 0x40);
+        rtc.tick(CPU_FREQ * 10);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0x40]);
+    }
+
+    #[test]
+    fn out_of_range_seconds_wrap_without_carrying() {
+        let mut rtc = Rtc::new();
+        rtc.write(0x08, 62);
+        rtc.advance(3);
+        assert_eq!(latched(&mut rtc), [1, 0, 0, 0, 0]);
+    }
+}
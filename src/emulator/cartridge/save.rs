@@ -0,0 +1,86 @@
+//! Battery backed ram is kept in a `.sav` file next to the rom
+//!
+//! The file is a raw dump of the cartridge ram, the same layout other emulators use so saves can
+//! be moved between them. Carts with a clock append the 48 byte footer popularised by VBA and
+//! BGB: the clock registers and latched registers as little endian u32s, then the UNIX time the
+//! save was written as a u64
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{rtc::Rtc, sibling_path};
+
+const RTC_REGISTERS_SIZE: usize = 40;
+const RTC_FOOTER_SIZE: usize = 48;
+// Some emulators only store a 32-bit timestamp
+const SHORT_RTC_FOOTER_SIZE: usize = 44;
+
+/// `<rom name>.sav` in the rom's directory
+pub fn save_path(rom_path: &Path) -> PathBuf {
+    sibling_path(rom_path, "sav")
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+fn encode_rtc(rtc: &mut Rtc) -> Vec<u8> {
+    let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+    for register in rtc.snapshot().into_iter().chain(rtc.latched()) {
+        footer.extend(u32::from(register).to_le_bytes());
+    }
+    footer.extend(unix_time().to_le_bytes());
+    footer
+}
+
+// Restores the clock, then catches it up on the time that passed while the game was closed
+fn decode_rtc(rtc: &mut Rtc, footer: &[u8]) {
+    let registers: Vec<u8> = footer[..RTC_REGISTERS_SIZE]
+        .chunks_exact(4)
+        .map(|register| register[0])
+        .collect();
+    let timestamp = match footer.len() {
+        RTC_FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+        _ => u64::from(u32::from_le_bytes(footer[40..44].try_into().unwrap())),
+    };
+
+    rtc.restore(
+        registers[..5].try_into().unwrap(),
+        registers[5..].try_into().unwrap(),
+    );
+    rtc.advance(unix_time().saturating_sub(timestamp));
+}
+
+/// Fills `ram` and the clock from the save file, a missing file leaves them untouched
+pub fn load(path: &Path, ram: &mut [u8], rtc: Option<&mut Rtc>) -> io::Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    // Saves from emulators that pad or trim the dump still load as much as fits
+    let len = data.len().min(ram.len());
+    ram[..len].copy_from_slice(&data[..len]);
+
+    let footer = &data[len..];
+    if let Some(rtc) = rtc {
+        if matches!(footer.len(), RTC_FOOTER_SIZE | SHORT_RTC_FOOTER_SIZE) {
+            decode_rtc(rtc, footer);
+        }
+    }
+    Ok(())
+}
+
+pub fn write(path: &Path, ram: &[u8], rtc: Option<&mut Rtc>) -> io::Result<()> {
+    let mut data = ram.to_vec();
+    if let Some(rtc) = rtc {
+        data.extend(encode_rtc(rtc));
+    }
+    fs::write(path, data)
+}
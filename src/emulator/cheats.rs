@@ -0,0 +1,332 @@
+//! Game Genie and GameShark codes
+//!
+//! A Game Genie sits between the cartridge and the console and swaps out bytes as they're read
+//! from rom, optionally only when the original byte matches a compare value so a code only hits
+//! the bank it was made for. A GameShark instead writes values into ram once per frame, at the
+//! start of VBlank.
+//!
+//! Cheats for a rom are kept in a `.cht` file next to it as JSON, saved whenever they change.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub use super::errors::CheatError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatEffect {
+    /// Game Genie, replaces the rom byte at `address` when it reads as `compare`, or always
+    /// without one
+    RomPatch {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// GameShark, writes `value` to `address` every VBlank. `bank` picks the external ram bank
+    /// for addresses in 0xA000..=0xBFFF, otherwise the write goes through the bus as the cpu's
+    /// would
+    RamWrite {
+        bank: Option<u8>,
+        address: u16,
+        value: u8,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    /// The code as entered, normalised to upper case
+    pub code: String,
+    pub effect: CheatEffect,
+    pub enabled: bool,
+}
+
+fn hex_digits(code: &str) -> Result<Vec<u8>, CheatError> {
+    code.chars()
+        .filter(|c| *c != '-')
+        .map(|c| {
+            c.to_digit(16)
+                .map(|digit| digit as u8)
+                .ok_or(CheatError::InvalidDigit(c))
+        })
+        .collect()
+}
+
+// ABC-DEF-GHI: AB is the new value, FCDE the address with F inverted, and GI the compare value
+// scrambled by a rotate and xor. H is a check digit the hardware ignores
+fn decode_game_genie(digits: &[u8]) -> CheatEffect {
+    let value = (digits[0] << 4) | digits[1];
+    let address = (u16::from(digits[5] ^ 0xF) << 12)
+        | (u16::from(digits[2]) << 8)
+        | (u16::from(digits[3]) << 4)
+        | u16::from(digits[4]);
+    let compare =
+        (digits.len() == 9).then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+    CheatEffect::RomPatch {
+        address,
+        value,
+        compare,
+    }
+}
+
+// TTVVLLHH: a type byte, the value, then the address low byte first. 0x01 is a plain write and
+// 0x8X names the external ram bank. 0x90..=0x97 name a CGB WRAM bank, DMG only has the one so
+// those are plain writes too
+fn decode_game_shark(digits: &[u8]) -> Result<CheatEffect, CheatError> {
+    let bytes: Vec<u8> = digits
+        .chunks_exact(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect();
+    let bank = match bytes[0] {
+        0x00 | 0x01 | 0x90..=0x97 => None,
+        kind @ 0x80..=0x8F => Some(kind & 0x0F),
+        kind => return Err(CheatError::UnknownType(kind)),
+    };
+    Ok(CheatEffect::RamWrite {
+        bank,
+        address: u16::from_le_bytes([bytes[2], bytes[3]]),
+        value: bytes[1],
+    })
+}
+
+impl Cheat {
+    /// Reads a Game Genie code, `ABC-DEF` or `ABC-DEF-GHI`, or an 8 digit GameShark code.
+    /// Dashes and whitespace are optional and the case doesn't matter
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        let code: String = code
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase();
+        let digits = hex_digits(&code)?;
+        let effect = match digits.len() {
+            6 | 9 => decode_game_genie(&digits),
+            8 if !code.contains('-') => decode_game_shark(&digits)?,
+            _ => return Err(CheatError::UnknownFormat(code)),
+        };
+        Ok(Self {
+            code,
+            effect,
+            enabled: true,
+        })
+    }
+}
+
+// What's kept in the cheat file, codes are decoded again on load
+#[derive(Serialize, Deserialize)]
+struct SavedCheat {
+    code: String,
+    enabled: bool,
+}
+
+/// The cheats for the loaded rom
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    // None for roms loaded from memory, their cheats only last until the next rom
+    path: Option<PathBuf>,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the cheats saved at `path`, a missing or unreadable file starts with none
+    pub fn load(path: Option<&Path>) -> Self {
+        let mut cheats = Self {
+            cheats: Vec::new(),
+            path: path.map(Path::to_path_buf),
+        };
+        let Some(data) = path.and_then(|path| fs::read_to_string(path).ok()) else {
+            return cheats;
+        };
+
+        let saved: Vec<SavedCheat> = match serde_json::from_str(&data) {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("Unable to parse cheat file: {e}");
+                return cheats;
+            }
+        };
+        for saved in saved {
+            match Cheat::parse(&saved.code) {
+                Ok(cheat) => cheats.cheats.push(Cheat {
+                    enabled: saved.enabled,
+                    ..cheat
+                }),
+                Err(e) => eprintln!("Skipping saved cheat '{}': {e}", saved.code),
+            }
+        }
+        cheats
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let saved: Vec<SavedCheat> = self
+            .cheats
+            .iter()
+            .map(|cheat| SavedCheat {
+                code: cheat.code.clone(),
+                enabled: cheat.enabled,
+            })
+            .collect();
+        let data = serde_json::to_string_pretty(&saved).expect("Unable to serialize cheats");
+        if let Err(e) = fs::write(path, data) {
+            eprintln!("Unable to save cheats to {}: {e}", path.display());
+        }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Adds an enabled cheat, returning it decoded
+    pub fn add(&mut self, code: &str) -> Result<Cheat, CheatError> {
+        let cheat = Cheat::parse(code)?;
+        self.cheats.push(cheat.clone());
+        self.save();
+        Ok(cheat)
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.cheats.len() {
+            self.cheats.remove(index);
+            self.save();
+        }
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+            self.save();
+        }
+    }
+
+    /// The byte the cpu sees when reading `value` from rom at `addr`
+    pub fn patch_rom(&self, addr: u16, value: u8) -> u8 {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .find_map(|cheat| match cheat.effect {
+                CheatEffect::RomPatch {
+                    address,
+                    value: patched,
+                    compare,
+                } if address == addr && compare.is_none_or(|compare| compare == value) => {
+                    Some(patched)
+                }
+                _ => None,
+            })
+            .unwrap_or(value)
+    }
+
+    /// The enabled GameShark writes as `(bank, address, value)`
+    pub fn ram_writes(&self) -> Vec<(Option<u8>, u16, u8)> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                CheatEffect::RamWrite {
+                    bank,
+                    address,
+                    value,
+                } => Some((bank, address, value)),
+                CheatEffect::RomPatch { .. } => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_codes_decode() {
+        let cheat = Cheat::parse("00a-17b-c49").unwrap();
+        assert_eq!(cheat.code, "00A-17B-C49");
+        assert_eq!(
+            cheat.effect,
+            CheatEffect::RomPatch {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            }
+        );
+
+        let cheat = Cheat::parse("3E1-2DF").unwrap();
+        assert_eq!(
+            cheat.effect,
+            CheatEffect::RomPatch {
+                address: 0x012D,
+                value: 0x3E,
+                compare: None,
+            }
+        );
+    }
+
+    #[test]
+    fn game_shark_codes_decode() {
+        let cheat = Cheat::parse("0163D2C0").unwrap();
+        assert_eq!(
+            cheat.effect,
+            CheatEffect::RamWrite {
+                bank: None,
+                address: 0xC0D2,
+                value: 0x63,
+            }
+        );
+
+        let cheat = Cheat::parse("8299 10 A0").unwrap();
+        assert_eq!(
+            cheat.effect,
+            CheatEffect::RamWrite {
+                bank: Some(2),
+                address: 0xA010,
+                value: 0x99,
+            }
+        );
+
+        let cheat = Cheat::parse("9163D2D0").unwrap();
+        assert_eq!(
+            cheat.effect,
+            CheatEffect::RamWrite {
+                bank: None,
+                address: 0xD0D2,
+                value: 0x63,
+            }
+        );
+
+        assert!(matches!(
+            Cheat::parse("4263D2C0"),
+            Err(CheatError::UnknownType(0x42))
+        ));
+        assert!(matches!(
+            Cheat::parse("0163-D2C0"),
+            Err(CheatError::UnknownFormat(_))
+        ));
+        assert!(matches!(
+            Cheat::parse("00G-17B"),
+            Err(CheatError::InvalidDigit('G'))
+        ));
+    }
+
+    #[test]
+    fn compare_value_limits_rom_patches() {
+        let mut cheats = Cheats::new();
+        cheats.add("00A-17B-C49").unwrap();
+
+        assert_eq!(cheats.patch_rom(0x4A17, 0xC8), 0x00);
+        // Another bank mapped at the same address is left alone
+        assert_eq!(cheats.patch_rom(0x4A17, 0x12), 0x12);
+        assert_eq!(cheats.patch_rom(0x4A18, 0xC8), 0xC8);
+
+        cheats.set_enabled(0, false);
+        assert_eq!(cheats.patch_rom(0x4A17, 0xC8), 0xC8);
+    }
+}
@@ -12,7 +12,11 @@ use crate::{
     },
     utils::bit_ops::BitOps,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 enum Direction {
     Left,
     Right,
@@ -47,6 +51,7 @@ impl DataType {
         }
     }
 
+    #[allow(dead_code)]
     fn as_u8(&self) -> Option<u8> {
         if let DataType::ValueU8(v) = self {
             Some(*v)
@@ -77,6 +82,10 @@ pub struct Cpu<B: Bus> {
     normal_opcodes: HashMap<u8, Opcode>,
     prefixed_opcodes: HashMap<u8, Opcode>,
     memory: Rc<RefCell<B>>,
+    // T-cycles of the current instruction the bus has been clocked through
+    bus_cycles: Cell<usize>,
+    // Cycle within the current instruction the next memory access happens on
+    next_access: Cell<usize>,
 }
 
 impl<B: Bus> Cpu<B> {
@@ -86,6 +95,8 @@ impl<B: Bus> Cpu<B> {
             normal_opcodes: Opcode::generate_normal_opcode_map(),
             prefixed_opcodes: Opcode::generate_prefixed_opcode_map(),
             memory,
+            bus_cycles: Cell::new(0),
+            next_access: Cell::new(0),
         }
     }
 
@@ -98,10 +109,45 @@ impl<B: Bus> Cpu<B> {
     }
 
     // Utility methods
+
+    // Starts clocking the bus for an instruction whose first memory access lands on `first_access`
+    fn begin_bus_cycles(&self, first_access: usize) {
+        self.bus_cycles.set(0);
+        self.next_access.set(first_access);
+    }
+
+    // Clocks the bus up to `cycle` within the current instruction
+    fn tick_bus_to(&self, cycle: usize) {
+        let ticked = self.bus_cycles.get();
+        if cycle > ticked {
+            self.memory.borrow_mut().tick(cycle - ticked);
+            self.bus_cycles.set(cycle);
+        }
+    }
+
+    // An M-cycle passes without touching memory
+    fn skip_access(&self) {
+        self.next_access.set(self.next_access.get() + 4);
+    }
+
+    // Brings the bus up to the M-cycle of the next memory access, so timers and the APU see reads
+    // and writes at the right time
+    fn sync_access(&self) {
+        self.tick_bus_to(self.next_access.get());
+        self.skip_access();
+    }
+
     fn write_mem_u8(&self, addr: u16, value: u8) {
+        self.sync_access();
         self.memory.borrow_mut().write_u8(addr, value);
     }
 
+    fn read_data_u8(&self, addr: u16) -> u8 {
+        self.sync_access();
+        self.memory.borrow().read_u8(addr)
+    }
+
+    // Opcode and operand fetches, these are already accounted for when an instruction starts
     fn read_mem_u8(&self, addr: u16) -> u8 {
         self.memory.borrow().read_u8(addr)
     }
@@ -113,7 +159,7 @@ impl<B: Bus> Cpu<B> {
     fn resolve_u8(&self, addressing_mode: &AddressingMode) -> Option<u8> {
         match self.get_data(addressing_mode) {
             DataType::ValueU8(val) => Some(val),
-            DataType::Address(addr) => Some(self.read_mem_u8(addr)),
+            DataType::Address(addr) => Some(self.read_data_u8(addr)),
             _ => None,
         }
     }
@@ -190,6 +236,8 @@ impl<B: Bus> Cpu<B> {
     pub fn push_stack(&mut self, value: u16) {
         let hi = ((value & 0xFF00) >> 8) as u8;
         let lo = (value & 0xFF) as u8;
+        // SP is decremented on its own M-cycle before the writes
+        self.skip_access();
         self.state.sp -= 1;
         self.write_mem_u8(self.state.sp, hi);
         self.state.sp -= 1;
@@ -197,9 +245,9 @@ impl<B: Bus> Cpu<B> {
     }
 
     pub fn pop_stack(&mut self) -> u16 {
-        let lo = self.read_mem_u8(self.state.sp);
+        let lo = self.read_data_u8(self.state.sp);
         self.state.sp += 1;
-        let hi = self.read_mem_u8(self.state.sp);
+        let hi = self.read_data_u8(self.state.sp);
         self.state.sp += 1;
         (u16::from(hi) << 8) | u16::from(lo)
     }
@@ -219,7 +267,7 @@ impl<B: Bus> Cpu<B> {
                 DataType::ValueU8(value) => self.set_immediate_register_u8(reg, value),
                 DataType::ValueU16(value) => self.set_immediate_register_u16(reg, value),
                 DataType::Address(addr) => {
-                    let value = self.read_mem_u8(addr);
+                    let value = self.read_data_u8(addr);
                     self.set_immediate_register_u8(reg, value);
                 }
                 _ => unreachable!("Should not have none or i8 here"),
@@ -418,7 +466,7 @@ impl<B: Bus> Cpu<B> {
         let mut extra_cycles = 0;
 
         if jump {
-            extra_cycles = 4;
+            extra_cycles = 12;
             let addr = self
                 .get_data(addressing_mode)
                 .as_address()
@@ -447,6 +495,8 @@ impl<B: Bus> Cpu<B> {
         };
 
         if jump {
+            // The condition is checked on its own M-cycle before popping
+            self.skip_access();
             self.state.pc = self.pop_stack();
             12
         } else {
@@ -854,7 +904,7 @@ impl<B: Bus> Cpu<B> {
     fn set_bit(&mut self, bit: u8, addressing_mode: &AddressingMode) {
         let (mut byte, addr) = match self.get_data(addressing_mode) {
             DataType::ValueU8(val) => (val, None),
-            DataType::Address(addr) => (self.read_mem_u8(addr), Some(addr)),
+            DataType::Address(addr) => (self.read_data_u8(addr), Some(addr)),
             _ => unreachable!("Should not have any other type here"),
         };
         byte.set_bit(bit);
@@ -871,7 +921,7 @@ impl<B: Bus> Cpu<B> {
     fn reset_bit(&mut self, bit: u8, addressing_mode: &AddressingMode) {
         let (mut byte, addr) = match self.get_data(addressing_mode) {
             DataType::ValueU8(val) => (val, None),
-            DataType::Address(addr) => (self.read_mem_u8(addr), Some(addr)),
+            DataType::Address(addr) => (self.read_data_u8(addr), Some(addr)),
             _ => unreachable!("Should not have any other type here"),
         };
         byte.clear_bit(bit);
@@ -938,11 +988,45 @@ impl<B: Bus> Cpu<B> {
         self.state.pc = addr;
     }
 
+    fn interrupt_pending(&self) -> bool {
+        let interrupt_enable = self.read_mem_u8(0xFFFF);
+        let interrupt_flag = self.read_mem_u8(0xFF0F);
+        (interrupt_enable & interrupt_flag & 0x1F) != 0
+    }
+
+    fn halt(&mut self) {
+        // With IME off and an interrupt already pending the cpu doesn't halt,
+        // instead the next byte gets read twice (HALT bug)
+        if !self.state.ime && self.interrupt_pending() {
+            self.state.halt_bug = true;
+        } else {
+            self.state.halted = true;
+        }
+    }
+
     pub fn execute_next_opcode(&mut self, debug_ctx: &mut DebugCtx<B>) -> Result<usize, CpuError> {
+        // Stay halted until an enabled interrupt is requested, regardless of IME.
+        // If IME is set the interrupt gets serviced by handle_interrupts
+        if self.state.halted {
+            if self.interrupt_pending() {
+                self.state.halted = false;
+            }
+            self.begin_bus_cycles(0);
+            self.tick_bus_to(4);
+            return Ok(4);
+        }
+
         // Get next instruction
         let mut code = self.read_mem_u8(self.state.pc);
         let prefixed = code == 0xcb;
 
+        // PC fails to increment after the opcode fetch, so operands are read starting
+        // from the opcode itself. Moving PC back one byte gives the same result
+        if self.state.halt_bug {
+            self.state.halt_bug = false;
+            self.state.pc = self.state.pc.wrapping_sub(1);
+        }
+
         let (_, opcode_bytes, opcode_cycles, lhs, rhs) = {
             let opcode_set = if prefixed {
                 code = self.read_mem_u8(self.state.pc.wrapping_add(1));
@@ -974,6 +1058,9 @@ impl<B: Bus> Cpu<B> {
 
         debug_ctx.push_call_log(self.state.pc, code, prefixed);
 
+        // Opcode and operand bytes take an M-cycle each, data accesses come after them
+        self.begin_bus_cycles(usize::from(opcode_bytes) * 4);
+
         // Execute instruction
         let mut skip_pc_increase = false;
         let mut extra_cycles: usize = 0;
@@ -1021,7 +1108,7 @@ impl<B: Bus> Cpu<B> {
                 0x03 | 0x13 | 0x23 | 0x33 => self.increment_u16(&lhs),
                 0x0b | 0x1b | 0x2b | 0x3b => self.decrement_u16(&lhs),
                 0x09 | 0x19 | 0x29 | 0x39 => self.add_hl_u16(&rhs),
-                0x76 => (), // TODO: HALT OPCODE
+                0x76 => self.halt(),
                 0x01
                 | 0x02
                 | 0x06
@@ -1055,7 +1142,7 @@ impl<B: Bus> Cpu<B> {
                 0x0f => self.rotate(&lhs, Direction::Right, false, false),
                 0x17 => self.rotate(&lhs, Direction::Left, false, true),
                 0x1f => self.rotate(&lhs, Direction::Right, false, true),
-                0x18 => extra_cycles = self.rel_jump(&rhs, None),
+                0x18 => _ = self.rel_jump(&rhs, None),
                 0x20 => extra_cycles = self.rel_jump(&rhs, Some(JumpCondition::NZ)),
                 0x28 => extra_cycles = self.rel_jump(&rhs, Some(JumpCondition::Z)),
                 0x30 => extra_cycles = self.rel_jump(&rhs, Some(JumpCondition::NC)),
@@ -1204,7 +1291,9 @@ impl<B: Bus> Cpu<B> {
         if !skip_pc_increase {
             self.state.pc = self.state.pc.wrapping_add(opcode_bytes);
         }
-        Ok(opcode_cycles + extra_cycles)
+        let cycles = opcode_cycles + extra_cycles;
+        self.tick_bus_to(cycles);
+        Ok(cycles)
     }
 
     pub fn handle_interrupts(&mut self, debug_ctx: &mut DebugCtx<B>) -> Option<usize> {
@@ -1224,9 +1313,12 @@ impl<B: Bus> Cpu<B> {
         for bit in 0..5 {
             if triggered_interrupts.get_bit(bit) != 0 {
                 self.state.ime = false;
+                self.state.halted = false;
                 interrupt_flag.clear_bit(bit);
                 self.memory.borrow_mut().write_u8(0xFF0F, interrupt_flag);
 
+                // Two wait states, then PC is pushed
+                self.begin_bus_cycles(4);
                 self.push_stack(self.state.pc);
                 self.state.pc = match bit {
                     0 => 0x40,
@@ -1237,17 +1329,19 @@ impl<B: Bus> Cpu<B> {
                     _ => unreachable!(),
                 };
                 debug_ctx.push_note(format!("triggered interrupt: {:4x}", self.state.pc));
-                debug_ctx.dump_logs();
+                self.tick_bus_to(20);
                 return Some(20);
             }
         }
         None
     }
 
+    #[cfg(test)]
     pub fn load_state(&mut self, state: CpuState) {
         self.state = state;
     }
 
+    #[allow(dead_code)]
     pub fn get_state(&self) -> CpuState {
         self.state.clone()
     }
@@ -13,6 +13,8 @@ pub struct CpuState {
     pub sp: u16,
     pub pc: u16,
     pub ime: bool,
+    pub halted: bool,
+    pub halt_bug: bool,
 }
 
 impl CpuState {
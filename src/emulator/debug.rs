@@ -63,6 +63,7 @@ impl Tile {
     }
 }
 
+#[allow(dead_code)]
 #[derive(PartialEq, Clone)]
 pub enum DebugFlag {
     ShowTileMap,
@@ -133,13 +134,12 @@ impl<B: Bus> DebugCtx<B> {
     }
 
     fn decode_instr(&self, opcode: &u8, preifxed: bool) -> String {
-        let instr;
-        if preifxed {
-            instr = self.prefix_opcode_map.get(opcode).unwrap();
+        let instr = if preifxed {
+            self.prefix_opcode_map.get(opcode).unwrap()
         } else {
-            instr = self.opcode_map.get(opcode).unwrap();
-        }
-        format!("{}", instr.asm)
+            self.opcode_map.get(opcode).unwrap()
+        };
+        instr.asm.to_string()
     }
 
     pub fn build_call_log(&self) -> Option<String> {
@@ -0,0 +1,64 @@
+const OAM_SIZE: u16 = 0xA0;
+// M-cycles between writing the DMA register and the first byte being copied
+const DMA_STARTUP_DELAY: usize = 1;
+
+pub struct Dma {
+    register: u8,
+    source: u16,
+    index: u16,
+    startup: Option<usize>,
+    active: bool,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self {
+            register: 0xFF,
+            source: 0,
+            index: 0,
+            startup: None,
+            active: false,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    /// Requests a transfer from `value`00, a transfer already running keeps going until the new
+    /// one takes over
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        self.startup = Some(DMA_STARTUP_DELAY);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Advances the transfer by one M-cycle, returns the source address and OAM offset of the
+    /// byte to copy this cycle
+    pub fn step(&mut self) -> Option<(u16, usize)> {
+        match self.startup {
+            Some(0) => {
+                self.startup = None;
+                self.source = u16::from(self.register) << 8;
+                self.index = 0;
+                self.active = true;
+            }
+            Some(delay) => self.startup = Some(delay - 1),
+            None => (),
+        }
+
+        if !self.active {
+            return None;
+        }
+
+        let transfer = (self.source + self.index, self.index as usize);
+        self.index += 1;
+        if self.index >= OAM_SIZE {
+            self.active = false;
+        }
+        Some(transfer)
+    }
+}
@@ -1,5 +1,6 @@
-use std::fmt;
+use std::{fmt, io};
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum MemError {
     OutOfRange,
@@ -15,6 +16,7 @@ impl fmt::Display for MemError {
 
 impl std::error::Error for MemError {}
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum CpuError {
     OpcodeNotImplemented(u8, bool),
@@ -60,6 +62,7 @@ impl fmt::Display for CpuError {
 
 impl std::error::Error for CpuError {}
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum EmulatorError {
     IncompatibleRom,
@@ -76,3 +79,111 @@ impl fmt::Display for EmulatorError {
 }
 
 impl std::error::Error for EmulatorError {}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    // Holds the file's length, the header ends at 0x150
+    TooShort(usize),
+    UnknownType(u8),
+    // Holds the cartridge type of a known controller that isn't emulated yet
+    UnsupportedMbc(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    HeaderChecksum {
+        expected: u8,
+        actual: u8,
+    },
+    Archive(String),
+    NoRomInArchive,
+    BadPatch(String),
+    PatchChecksum {
+        part: &'static str,
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(e) => write!(f, "Unable to read rom: {e}"),
+            CartridgeError::TooShort(len) => {
+                write!(
+                    f,
+                    "Rom is {len} bytes, too short to hold a cartridge header"
+                )
+            }
+            CartridgeError::UnknownType(code) => write!(f, "Unknown cartridge type {code:#04x}"),
+            CartridgeError::UnsupportedMbc(code) => {
+                write!(
+                    f,
+                    "Cartridge type {code:#04x} uses an MBC that isn't supported yet"
+                )
+            }
+            CartridgeError::UnknownRomSize(code) => write!(f, "Unknown rom size {code:#04x}"),
+            CartridgeError::UnknownRamSize(code) => write!(f, "Unknown ram size {code:#04x}"),
+            CartridgeError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Header declares a {expected} byte rom but the file is {actual} bytes"
+            ),
+            CartridgeError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "Header checksum is {expected:#04x} but the header sums to {actual:#04x}"
+            ),
+            CartridgeError::Archive(e) => write!(f, "Unable to read archive: {e}"),
+            CartridgeError::NoRomInArchive => write!(f, "Archive has no .gb or .gbc file in it"),
+            CartridgeError::BadPatch(reason) => write!(f, "Unable to apply patch: {reason}"),
+            CartridgeError::PatchChecksum {
+                part,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Patch expects a {part} CRC32 of {expected:08x} but got {actual:08x}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(e: io::Error) -> Self {
+        CartridgeError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+pub enum CheatError {
+    /// Not 6 or 9 digits for a Game Genie code or 8 for a GameShark code
+    UnknownFormat(String),
+    InvalidDigit(char),
+    /// The GameShark code type in the first byte
+    UnknownType(u8),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::UnknownFormat(code) => {
+                write!(f, "'{code}' is not a Game Genie or GameShark code")
+            }
+            CheatError::InvalidDigit(c) => write!(f, "'{c}' is not a hex digit"),
+            CheatError::UnknownType(kind) => write!(f, "Unknown GameShark code type {kind:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
@@ -0,0 +1,120 @@
+use crate::utils::bit_ops::BitOps;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // Bit of the P1 lower nibble this button pulls low when selected
+    fn bit(&self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0,
+            Button::Left | Button::B => 1,
+            Button::Up | Button::Select => 2,
+            Button::Down | Button::Start => 3,
+        }
+    }
+
+    fn is_direction(&self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+impl std::fmt::Display for Button {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Button::Right => write!(f, "Right"),
+            Button::Left => write!(f, "Left"),
+            Button::Up => write!(f, "Up"),
+            Button::Down => write!(f, "Down"),
+            Button::A => write!(f, "A"),
+            Button::B => write!(f, "B"),
+            Button::Select => write!(f, "Select"),
+            Button::Start => write!(f, "Start"),
+        }
+    }
+}
+
+pub struct Joypad {
+    // P1 bits 4 and 5, a 0 selects the group
+    select: u8,
+    // Pressed buttons are stored as 1s, P1 reports them inverted
+    directions: u8,
+    actions: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self {
+            select: 0x30,
+            directions: 0,
+            actions: 0,
+        }
+    }
+
+    // Lower nibble of P1, both groups are wired together if both are selected
+    fn lines(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select.get_bit(4) == 0 {
+            pressed |= self.directions;
+        }
+        if self.select.get_bit(5) == 0 {
+            pressed |= self.actions;
+        }
+        !pressed & 0x0F
+    }
+
+    // The joypad interrupt is requested when any input line goes from high to low
+    fn falling_edge(before: u8, after: u8) -> bool {
+        (before & !after) & 0x0F != 0
+    }
+
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    /// Updates the select bits, returns true if a joypad interrupt was requested
+    pub fn write(&mut self, value: u8) -> bool {
+        let before = self.lines();
+        self.select = value & 0x30;
+        Self::falling_edge(before, self.lines())
+    }
+
+    /// Presses or releases a button, returns true if a joypad interrupt was requested
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.lines();
+        let group = if button.is_direction() {
+            &mut self.directions
+        } else {
+            &mut self.actions
+        };
+        if pressed {
+            group.set_bit(button.bit());
+        } else {
+            group.clear_bit(button.bit());
+        }
+        Self::falling_edge(before, self.lines())
+    }
+}
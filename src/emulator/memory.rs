@@ -1,31 +1,62 @@
-use std::{fs, ops::Range};
+use std::{fs, io, ops::Range};
 
-use super::cartridge::Cartridge;
+#[cfg(test)]
+use super::serial::SerialDevice;
+use super::{
+    apu::Apu,
+    cartridge::{Cartridge, CartridgeHeader},
+    cheats::Cheats,
+    dma::Dma,
+    joypad::{Button, Joypad},
+    serial::Serial,
+    timer::Timer,
+};
+use crate::utils::bit_ops::BitOps;
 
 pub trait Bus {
     fn read_u8(&self, addr: u16) -> u8;
     fn write_u8(&mut self, addr: u16, data: u8);
     fn read_u16(&self, addr: u16) -> u16;
+    #[allow(dead_code)]
     fn clear(&mut self);
     fn get_range(&self, range: Range<u16>) -> Vec<u8>;
     fn load_cartridge(&mut self, cartridge: Cartridge);
     fn raw_read(&self, addr: u16) -> u8;
     fn raw_write(&mut self, addr: u16, value: u8);
+    /// Advances the components living on the bus by `cycles` T-cycles
+    fn tick(&mut self, cycles: usize);
+    fn set_button(&mut self, button: Button, pressed: bool);
+    fn take_audio_samples(&mut self) -> Vec<(f32, f32)>;
+    #[cfg(test)]
+    fn set_serial_device(&mut self, device: Box<dyn SerialDevice>);
+    /// Whether the cartridge's rumble motor is on
+    fn rumbling(&self) -> bool;
+    /// Writes battery backed cartridge ram to its save file
+    fn save_cartridge(&mut self) -> io::Result<()>;
+    fn cartridge_header(&self) -> Option<CartridgeHeader>;
+    fn cheats(&self) -> &Cheats;
+    fn cheats_mut(&mut self) -> &mut Cheats;
+    /// Writes the enabled GameShark codes into ram, done as VBlank starts
+    fn apply_ram_cheats(&mut self);
 }
 
 pub struct DMGBus {
     boot_rom: Vec<u8>,
     vram: Vec<u8>,
-    ram: Vec<u8>,
     work_ram: Vec<u8>,
     oam: Vec<u8>,
     io_registers: Vec<u8>,
     hram: Vec<u8>,
 
     cartridge: Option<Cartridge>,
+    timer: Timer,
+    joypad: Joypad,
+    dma: Dma,
+    apu: Apu,
+    serial: Serial,
+    cheats: Cheats,
 
     boot_rom_active: bool,
-    current_bank: usize,
 }
 
 impl DMGBus {
@@ -43,22 +74,96 @@ impl DMGBus {
         Ok(DMGBus {
             boot_rom,
             vram: vec![0xFF; 0x2000],
-            ram: vec![0xFF; 0x2000],
             work_ram: vec![0xFF; 0x2000],
             oam: vec![0xFF; 0x00A0],
-            io_registers: vec![0xFF; 0x80],
+            io_registers: Self::initial_io_registers(),
             hram: vec![0xFF; 0x0080],
 
             cartridge: None,
+            timer: Timer::new(),
+            joypad: Joypad::new(),
+            dma: Dma::new(),
+            apu: Apu::new(),
+            serial: Serial::new(),
+            cheats: Cheats::new(),
 
             boot_rom_active: true,
-            current_bank: 1,
         })
     }
-}
 
-impl Bus for DMGBus {
-    fn read_u8(&self, addr: u16) -> u8 {
+    // No interrupts are pending or enabled as STAT sources at power on
+    fn initial_io_registers() -> Vec<u8> {
+        let mut io_registers = vec![0xFF; 0x80];
+        io_registers[0x0F] = 0x00;
+        io_registers[0x41] = 0x80;
+        io_registers
+    }
+
+    fn read_io(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF00 => self.joypad.read(),
+            0xFF01..=0xFF02 => self.serial.read(addr),
+            0xFF04..=0xFF07 => self.timer.read(addr),
+            0xFF10..=0xFF3F => self.apu.read(addr),
+            // Unused bits of IF and STAT read as 1
+            0xFF0F => self.io_registers[0x0F] | 0xE0,
+            0xFF41 => self.io_registers[0x41] | 0x80,
+            0xFF46 => self.dma.read(),
+            _ => self.io_registers[addr as usize - 0xFF00],
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF00 => {
+                if self.joypad.write(value) {
+                    self.request_interrupt(4);
+                }
+            }
+            0xFF01..=0xFF02 => self.serial.write(addr, value),
+            0xFF04..=0xFF07 => {
+                // Resetting DIV can clock the frame sequencer early
+                let apu_signal = self.timer.apu_signal();
+                self.timer.write(addr, value);
+                if apu_signal && !self.timer.apu_signal() {
+                    self.apu.step_frame_sequencer();
+                }
+            }
+            0xFF10..=0xFF3F => self.apu.write(addr, value),
+            0xFF46 => self.dma.write(value),
+            // Mode and LY=LYC bits are read only
+            0xFF41 => {
+                self.io_registers[0x41] = (self.io_registers[0x41] & 0x07) | (value & 0x78);
+            }
+            _ => self.io_registers[addr as usize - 0xFF00] = value,
+        }
+    }
+
+    fn request_interrupt(&mut self, bit: u8) {
+        self.io_registers[0x0F].set_bit(bit);
+    }
+
+    // While OAM DMA is running the cpu loses everything below the I/O registers, I/O, HRAM and
+    // IE stay reachable so interrupts, HALT and restarting the transfer keep working
+    fn dma_blocks(&self, addr: u16) -> bool {
+        self.dma.is_active() && addr < 0xFF00
+    }
+
+    fn tick_dma(&mut self, cycles: usize) {
+        for _ in 0..cycles / 4 {
+            if let Some((source, offset)) = self.dma.step() {
+                // Sources past WRAM read from echo RAM
+                let source = if source >= 0xE000 {
+                    source - 0x2000
+                } else {
+                    source
+                };
+                self.oam[offset] = self.read_mapped(source);
+            }
+        }
+    }
+
+    fn read_mapped(&self, addr: u16) -> u8 {
         if self.boot_rom_active {
             if let 0x0000..=0x00FF = addr {
                 return self.boot_rom[addr as usize];
@@ -68,23 +173,20 @@ impl Bus for DMGBus {
         let cartridge = self.cartridge.as_ref().unwrap();
 
         match addr {
-            0x0000..=0x7FFF => cartridge.read(addr),
+            0x0000..=0x7FFF => self.cheats.patch_rom(addr, cartridge.read(addr)),
             0x8000..=0x9FFF => self.vram[addr as usize - 0x8000],
-            0xA000..=0xBFFF => self.ram[addr as usize - 0xA000],
+            0xA000..=0xBFFF => cartridge.read_ram(addr),
             0xC000..=0xDFFF => self.work_ram[addr as usize - 0xC000],
             0xE000..=0xFDFF => self.work_ram[addr as usize - 0xE000],
             0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00],
             0xFEA0..=0xFEFF => 0x00, // not useable range, refer to pandocs
-            0xFF00..=0xFF7F => self.io_registers[addr as usize - 0xFF00],
+            0xFF00..=0xFF7F => self.read_io(addr),
             0xFF80..=0xFFFF => self.hram[addr as usize - 0xFF80],
         }
     }
 
-    fn write_u8(&mut self, addr: u16, value: u8) {
+    fn write_mapped(&mut self, addr: u16, value: u8) {
         // TODO: implement Echo RAM and range checks
-        // set DIV to 0 if it is written to
-        let value = if addr == 0xff04 { 0 } else { value };
-
         // boot rom writes to here to deactivate itself
         if addr == 0xff50 {
             self.boot_rom_active = false;
@@ -96,15 +198,34 @@ impl Bus for DMGBus {
                 cartridge.write(addr, value);
             }
             0x8000..=0x9FFF => self.vram[addr as usize - 0x8000] = value,
-            0xA000..=0xBFFF => self.ram[addr as usize - 0xA000] = value,
+            0xA000..=0xBFFF => {
+                let cartridge = self.cartridge.as_mut().unwrap();
+                cartridge.write_ram(addr, value);
+            }
             0xC000..=0xDFFF => self.work_ram[addr as usize - 0xC000] = value,
             0xE000..=0xFDFF => self.work_ram[addr as usize - 0xE000] = value,
             0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = value,
             0xFEA0..=0xFEFF => (), // not useable range, refer to pandocs
-            0xFF00..=0xFF7F => self.io_registers[addr as usize - 0xFF00] = value,
+            0xFF00..=0xFF7F => self.write_io(addr, value),
             0xFF80..=0xFFFF => self.hram[addr as usize - 0xFF80] = value,
         }
     }
+}
+
+impl Bus for DMGBus {
+    fn read_u8(&self, addr: u16) -> u8 {
+        if self.dma_blocks(addr) {
+            return 0xFF;
+        }
+        self.read_mapped(addr)
+    }
+
+    fn write_u8(&mut self, addr: u16, value: u8) {
+        if self.dma_blocks(addr) {
+            return;
+        }
+        self.write_mapped(addr, value);
+    }
 
     fn read_u16(&self, addr: u16) -> u16 {
         let lo = u16::from(self.read_u8(addr));
@@ -114,33 +235,117 @@ impl Bus for DMGBus {
 
     fn clear(&mut self) {
         self.vram = vec![0xFF; 0x2000];
-        self.ram = vec![0xFF; 0x2000];
         self.work_ram = vec![0xFF; 0x2000];
         self.oam = vec![0xFF; 0x00A0];
-        self.io_registers = vec![0xFF; 0x0080];
+        self.io_registers = Self::initial_io_registers();
         self.hram = vec![0xFF; 0x0080];
+        self.timer = Timer::new();
+        self.joypad = Joypad::new();
+        self.dma = Dma::new();
+        self.apu = Apu::new();
+        self.serial.reset();
     }
 
     fn get_range(&self, range: Range<u16>) -> Vec<u8> {
-        range.into_iter().map(|i| self.read_u8(i)).collect()
+        range.into_iter().map(|i| self.raw_read(i)).collect()
     }
 
     fn load_cartridge(&mut self, cartridge: Cartridge) {
+        self.cheats = Cheats::load(cartridge.cheats_path());
         self.cartridge = Some(cartridge);
     }
 
     fn raw_read(&self, addr: u16) -> u8 {
-        self.read_u8(addr)
+        self.read_mapped(addr)
     }
 
     fn raw_write(&mut self, addr: u16, value: u8) {
-        self.write_u8(addr, value);
+        // Lets the ppu update LCD registers that are read only to the cpu, DMA still has to start
+        // a transfer
+        if let 0xFF40..=0xFF45 | 0xFF47..=0xFF4B = addr {
+            self.io_registers[addr as usize - 0xFF00] = value;
+        } else {
+            self.write_mapped(addr, value);
+        }
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        let apu_signal = self.timer.apu_signal();
+        if self.timer.tick(cycles) {
+            self.request_interrupt(2);
+        }
+        if self.serial.tick(cycles) {
+            self.request_interrupt(3);
+        }
+        if apu_signal && !self.timer.apu_signal() {
+            self.apu.step_frame_sequencer();
+        }
+        self.apu.tick(cycles);
+        if let Some(cartridge) = self.cartridge.as_mut() {
+            cartridge.tick(cycles);
+        }
+        self.tick_dma(cycles);
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button(button, pressed) {
+            self.request_interrupt(4);
+        }
+    }
+
+    fn take_audio_samples(&mut self) -> Vec<(f32, f32)> {
+        self.apu.take_samples()
+    }
+
+    #[cfg(test)]
+    fn set_serial_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.serial.set_device(device);
+    }
+
+    fn rumbling(&self) -> bool {
+        self.cartridge
+            .as_ref()
+            .is_some_and(|cartridge| cartridge.rumbling())
+    }
+
+    fn save_cartridge(&mut self) -> io::Result<()> {
+        match self.cartridge.as_mut() {
+            Some(cartridge) => cartridge.save(),
+            None => Ok(()),
+        }
+    }
+
+    fn cartridge_header(&self) -> Option<CartridgeHeader> {
+        self.cartridge
+            .as_ref()
+            .map(|cartridge| cartridge.header().clone())
+    }
+
+    fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    fn apply_ram_cheats(&mut self) {
+        for (bank, addr, value) in self.cheats.ram_writes() {
+            match self.cartridge.as_mut() {
+                Some(cartridge) if (0xA000..=0xBFFF).contains(&addr) => {
+                    cartridge.write_cheat(bank, addr, value);
+                }
+                _ => self.write_mapped(addr, value),
+            }
+        }
     }
 }
 
 #[cfg(test)]
 pub struct RawBus {
     ram: Vec<u8>,
+    // Never applied, only here to satisfy the trait
+    cheats: Cheats,
 }
 
 #[cfg(test)]
@@ -148,6 +353,7 @@ impl RawBus {
     pub fn new() -> Self {
         Self {
             ram: vec![0; 0x10000],
+            cheats: Cheats::new(),
         }
     }
 }
@@ -184,4 +390,111 @@ impl Bus for RawBus {
     fn raw_write(&mut self, addr: u16, value: u8) {
         self.ram[addr as usize] = value;
     }
+
+    fn tick(&mut self, _cycles: usize) {}
+
+    fn set_button(&mut self, _button: Button, _pressed: bool) {}
+
+    fn take_audio_samples(&mut self) -> Vec<(f32, f32)> {
+        Vec::new()
+    }
+
+    fn set_serial_device(&mut self, _device: Box<dyn SerialDevice>) {}
+
+    fn rumbling(&self) -> bool {
+        false
+    }
+
+    fn save_cartridge(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn cartridge_header(&self) -> Option<CartridgeHeader> {
+        None
+    }
+    fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    fn apply_ram_cheats(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bus() -> DMGBus {
+        let mut bus = DMGBus::new().unwrap();
+        // Loaded from bytes so no cheat file is read or written next to the rom
+        let rom = fs::read("./roms/tests/dmg-acid2.gb").unwrap();
+        bus.load_cartridge(Cartridge::from_bytes(&rom).unwrap());
+        bus
+    }
+
+    #[test]
+    fn raw_write_to_dma_register_starts_transfer() {
+        let mut bus = bus();
+        bus.raw_write(0xC000, 0x12);
+        bus.raw_write(0xC09F, 0x34);
+        bus.raw_write(0xFF46, 0xC0);
+        assert_eq!(bus.raw_read(0xFF46), 0xC0);
+
+        // One M-cycle of setup, then a byte per M-cycle
+        bus.tick(4 * (1 + 0xA0));
+        assert_eq!(bus.raw_read(0xFE00), 0x12);
+        assert_eq!(bus.raw_read(0xFE9F), 0x34);
+    }
+    #[test]
+    fn dma_blocks_only_the_external_bus() {
+        let mut bus = bus();
+        bus.write_u8(0xFF80, 0x56);
+        bus.write_u8(0xFFFF, 0x1F);
+        bus.write_u8(0xFF46, 0xC0);
+        bus.tick(4 * 2); // past setup, the transfer is running
+
+        assert_eq!(bus.read_u8(0xC000), 0xFF);
+        assert_eq!(bus.read_u8(0xFF80), 0x56);
+        assert_eq!(bus.read_u8(0xFFFF), 0x1F);
+
+        bus.write_u8(0xC100, 0x12);
+        bus.write_u8(0xFF81, 0x78);
+        bus.write_u8(0xFF42, 0x9A);
+        bus.write_u8(0xFF0F, 0x01);
+        assert_ne!(bus.raw_read(0xC100), 0x12);
+        assert_eq!(bus.raw_read(0xFF81), 0x78);
+        assert_eq!(bus.raw_read(0xFF42), 0x9A);
+        assert_eq!(bus.read_u8(0xFF0F) & 0x1F, 0x01);
+    }
+    #[test]
+    fn dma_can_be_restarted_mid_transfer() {
+        let mut bus = bus();
+        for i in 0..0xA0 {
+            bus.raw_write(0xC000 + i, 0x11);
+            bus.raw_write(0xC100 + i, 0x22);
+        }
+        bus.write_u8(0xFF46, 0xC0);
+        bus.tick(4 * 0x40);
+        bus.write_u8(0xFF46, 0xC1);
+        assert_eq!(bus.raw_read(0xFF46), 0xC1);
+
+        bus.tick(4 * (1 + 0xA0));
+        for i in 0..0xA0 {
+            assert_eq!(bus.raw_read(0xFE00 + i), 0x22);
+        }
+    }
+    #[test]
+    fn game_shark_codes_write_wram_at_vblank() {
+        let mut bus = bus();
+        bus.cheats_mut().add("0163D2C0").unwrap();
+        // A CGB WRAM bank is plain WRAM on DMG
+        bus.cheats_mut().add("9142D2D0").unwrap();
+
+        bus.apply_ram_cheats();
+        assert_eq!(bus.raw_read(0xC0D2), 0x63);
+        assert_eq!(bus.raw_read(0xD0D2), 0x42);
+    }
 }
@@ -1,37 +1,47 @@
+mod apu;
+pub mod audio;
 pub mod cartridge;
+pub mod cheats;
 mod cpu;
 pub mod debug;
+mod dma;
 mod errors;
+mod joypad;
 mod memory;
 mod ppu;
+pub mod serial;
 mod test;
+mod timer;
 
 #[cfg(test)]
 use errors::CpuError;
 #[cfg(test)]
 pub use memory::RawBus;
 #[cfg(test)]
+use serial::SerialDevice;
+#[cfg(test)]
 use std::{fs, io::Write};
 #[cfg(test)]
 use test::TestCase;
 
 use std::{cell::RefCell, error::Error, rc::Rc};
 
-use crate::{utils::frame_buffer::FrameBuffer, Palette};
-use cartridge::Cartridge;
+use crate::{utils::frame_buffer::FrameBuffer, Palette, GRAY_PALETTE};
+use audio::{AudioOutput, AudioSink};
+use cartridge::{Cartridge, CartridgeHeader};
+use cheats::{Cheat, CheatError};
 use cpu::Cpu;
 use debug::{DebugCtx, DebugFlag};
 use errors::EmulatorError;
 use memory::Bus;
 use ppu::Ppu;
 
+pub use joypad::Button;
 pub use memory::DMGBus;
 pub use ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
 
 const CPU_FREQ: usize = 4_194_304; // T-cycles
-const DIV_FREQ: usize = 16_384;
 const MAX_CYCLES_PER_FRAME: usize = 70_224; // CPU_FREQ / FRAME_RATE
-const DIV_UPDATE_FREQ: usize = CPU_FREQ / DIV_FREQ;
 
 pub enum LCDRegister {
     Lcdc,
@@ -44,6 +54,8 @@ pub enum LCDRegister {
     Bgp,
     Obp0,
     Obp1,
+    Wy,
+    Wx,
 }
 
 impl From<LCDRegister> for u16 {
@@ -59,24 +71,26 @@ impl From<LCDRegister> for u16 {
             LCDRegister::Bgp => 0xff47,
             LCDRegister::Obp0 => 0xff48,
             LCDRegister::Obp1 => 0xff49,
+            LCDRegister::Wy => 0xff4a,
+            LCDRegister::Wx => 0xff4b,
         }
     }
 }
 
-enum Timer {
+pub enum TimerRegister {
     Div,
     Tima,
     Tma,
     Tac,
 }
 
-impl From<Timer> for u16 {
-    fn from(val: Timer) -> Self {
+impl From<TimerRegister> for u16 {
+    fn from(val: TimerRegister) -> Self {
         match val {
-            Timer::Div => 0xFF04,
-            Timer::Tima => 0xFF05,
-            Timer::Tma => 0xFF06,
-            Timer::Tac => 0xFF07,
+            TimerRegister::Div => 0xFF04,
+            TimerRegister::Tima => 0xFF05,
+            TimerRegister::Tma => 0xFF06,
+            TimerRegister::Tac => 0xFF07,
         }
     }
 }
@@ -103,10 +117,10 @@ pub struct Emulator<B: Bus> {
     ppu: Ppu<B>,
     memory: Rc<RefCell<B>>,
     debug_ctx: DebugCtx<B>,
-    timer_cycles: usize,
     frames: usize,
     running: RunType,
     cycles_this_frame: usize,
+    audio: Option<AudioOutput>,
 }
 
 impl Emulator<DMGBus> {
@@ -115,7 +129,7 @@ impl Emulator<DMGBus> {
         let memory_bus = DMGBus::new().unwrap();
         let memory_bus = Rc::new(RefCell::new(memory_bus));
 
-        let palette: Palette = (0xFFFFFF, 0xa9a9a9, 0x545454, 0x000000);
+        let palette: Palette = GRAY_PALETTE;
 
         let debug_ctx = DebugCtx::new(Rc::clone(&memory_bus), palette);
         let cpu = Cpu::new(Rc::clone(&memory_bus));
@@ -125,10 +139,10 @@ impl Emulator<DMGBus> {
             ppu: Ppu::new(Rc::clone(&memory_bus), palette),
             memory: Rc::clone(&memory_bus),
             debug_ctx,
-            timer_cycles: 0,
             frames: 0,
             running: RunType::Paused,
             cycles_this_frame: 0,
+            audio: None,
         }
     }
 }
@@ -138,7 +152,7 @@ impl Emulator<RawBus> {
     pub fn new() -> Self {
         let memory_bus = Rc::new(RefCell::new(RawBus::new()));
 
-        let palette: Palette = (0xFFFFFF, 0xa9a9a9, 0x545454, 0x000000);
+        let palette: Palette = GRAY_PALETTE;
 
         let debug_ctx = DebugCtx::new(Rc::clone(&memory_bus), palette);
 
@@ -147,10 +161,10 @@ impl Emulator<RawBus> {
             ppu: Ppu::new(Rc::clone(&memory_bus), palette),
             memory: Rc::clone(&memory_bus),
             debug_ctx,
-            timer_cycles: 0,
             frames: 0,
             running: RunType::Paused,
             cycles_this_frame: 0,
+            audio: None,
         }
     }
 }
@@ -167,11 +181,32 @@ impl<B: Bus> Emulator<B> {
         self
     }
 
+    #[cfg(test)]
+    pub fn with_audio_sink(mut self, sink: Box<dyn AudioSink>) -> Self {
+        self.set_audio_sink(sink);
+        self
+    }
+
     pub fn with_rom(self, rom: Cartridge) -> Result<Self, Box<dyn Error>> {
         self.load_rom(rom)?;
         Ok(self)
     }
 
+    #[cfg(test)]
+    pub fn with_serial_device(self, device: Box<dyn SerialDevice>) -> Self {
+        self.memory.borrow_mut().set_serial_device(device);
+        self
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio = Some(AudioOutput::new(sink));
+    }
+
+    /// Detaches the audio sink, dropping it finishes anything it was writing
+    pub fn clear_audio_sink(&mut self) {
+        self.audio = None;
+    }
+
     pub fn set_run_type(&mut self, run_type: RunType) {
         self.running = run_type;
     }
@@ -198,13 +233,33 @@ impl<B: Bus> Emulator<B> {
         }
     }
 
-    fn update_timers(&mut self, cycles: usize) {
-        self.timer_cycles += cycles;
-        if self.timer_cycles >= DIV_UPDATE_FREQ {
-            let addr = Timer::Div.into();
-            let div = self.memory.borrow().read_u8(addr);
-            self.memory.borrow_mut().write_u8(addr, div.wrapping_add(1));
-            self.timer_cycles = 0;
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.memory.borrow_mut().set_button(button, pressed);
+    }
+
+    /// Takes the audio produced since the last call, as stereo samples at `APU_SAMPLE_RATE`
+    pub fn take_audio_samples(&mut self) -> Vec<(f32, f32)> {
+        self.memory.borrow_mut().take_audio_samples()
+    }
+
+    // Samples are still drained without a sink so the APU's buffer doesn't fill up
+    fn flush_audio(&mut self) -> Result<(), Box<dyn Error>> {
+        let samples = self.take_audio_samples();
+        match self.audio.as_mut() {
+            Some(audio) => audio.push_samples(&samples),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the cartridge's battery backed ram to its save file if it changed
+    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        self.memory.borrow_mut().save_cartridge()?;
+        Ok(())
+    }
+
+    fn update_graphics(&mut self, cycles: usize) {
+        if self.ppu.update_graphics(cycles) {
+            self.memory.borrow_mut().apply_ram_cheats();
         }
     }
 
@@ -220,6 +275,14 @@ impl<B: Bus> Emulator<B> {
             self.tick_instr()?;
         }
         self.cycles_this_frame = 0;
+        self.flush_audio()?;
+
+        // Flushed about once a second so a crash loses little progress
+        if self.frames == 0 {
+            if let Err(e) = self.save() {
+                eprintln!("Unable to write save file: {e}");
+            }
+        }
 
         Ok(self.ppu.get_frame())
     }
@@ -232,13 +295,12 @@ impl<B: Bus> Emulator<B> {
             self.update_frame_count();
         }
 
-        self.update_timers(cycles);
-        self.ppu.update_graphics(cycles);
+        // The cpu clocks the bus itself so memory accesses land on the right M-cycle
+        self.update_graphics(cycles);
 
         if let Some(interrupt_cycles) = self.cpu.handle_interrupts(&mut self.debug_ctx) {
             self.cycles_this_frame += interrupt_cycles;
-            self.update_timers(cycles);
-            self.ppu.update_graphics(cycles);
+            self.update_graphics(interrupt_cycles);
         }
 
         Ok(())
@@ -251,12 +313,57 @@ impl<B: Bus> Emulator<B> {
             RunType::Instr => {
                 // Add logic for ticking only once
                 self.tick_instr()?;
+                self.flush_audio()?;
                 self.running = RunType::Paused;
                 Ok(self.ppu.get_frame())
             }
         }
     }
 
+    /// The last frame drawn, without advancing the emulator
+    #[cfg(test)]
+    pub fn frame(&self) -> &FrameBuffer {
+        self.ppu.get_frame()
+    }
+
+    /// Header of the loaded cartridge, None before a rom is loaded
+    pub fn cartridge_header(&self) -> Option<CartridgeHeader> {
+        self.memory.borrow().cartridge_header()
+    }
+
+    /// The loaded rom's cheats, in the order they were added
+    pub fn cheats(&self) -> Vec<Cheat> {
+        self.memory.borrow().cheats().list().to_vec()
+    }
+
+    /// Adds and enables a Game Genie or GameShark code, saving it with the rom's other cheats
+    pub fn add_cheat(&mut self, code: &str) -> Result<Cheat, CheatError> {
+        self.memory.borrow_mut().cheats_mut().add(code)
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) {
+        self.memory.borrow_mut().cheats_mut().remove(index);
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        self.memory
+            .borrow_mut()
+            .cheats_mut()
+            .set_enabled(index, enabled);
+    }
+
+    /// Whether a rumble cartridge currently has its motor on, frontends can poll this each frame
+    pub fn rumbling(&self) -> bool {
+        self.memory.borrow().rumbling()
+    }
+
+    /// Address and opcode of the next instruction the cpu will run
+    #[cfg(test)]
+    pub fn next_instruction(&self) -> (u16, u8) {
+        let pc = self.cpu.get_state().pc;
+        (pc, self.memory.borrow().raw_read(pc))
+    }
+
     pub fn debug_ctx(&self) -> &DebugCtx<B> {
         &self.debug_ctx
     }
@@ -280,6 +387,8 @@ impl<B: Bus> Emulator<B> {
             sp: test.initial.sp,
             pc: test.initial.pc,
             ime: false,
+            halted: false,
+            halt_bug: false,
         };
         self.cpu.load_state(cpu_state);
         self.memory.borrow_mut().clear();
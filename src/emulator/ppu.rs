@@ -3,7 +3,7 @@ use std::collections::VecDeque;
 use std::rc::Rc;
 
 use super::memory::Bus;
-use super::{debug::DebugCtx, LCDRegister};
+use super::LCDRegister;
 use crate::utils::bit_ops::BitOps;
 use crate::utils::frame_buffer::FrameBuffer;
 use crate::Palette;
@@ -11,7 +11,11 @@ pub const SCREEN_WIDTH: usize = 160;
 pub const SCREEN_HEIGHT: usize = 144;
 
 const CYCLES_PER_SCANLINE: usize = 456;
+const MAX_SPRITES_PER_LINE: usize = 10;
+const SPRITE_FETCH_CYCLES: usize = 6;
+const LINE_153_LY_RESET_CYCLES: usize = 4;
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum PpuMode {
     HBlank,
     VBlank,
@@ -19,6 +23,18 @@ enum PpuMode {
     DrawingPixels,
 }
 
+impl From<PpuMode> for u8 {
+    fn from(val: PpuMode) -> Self {
+        match val {
+            PpuMode::HBlank => 0,
+            PpuMode::VBlank => 1,
+            PpuMode::OAMScan => 2,
+            PpuMode::DrawingPixels => 3,
+        }
+    }
+}
+
+#[allow(dead_code)]
 enum FetcherMode {
     GetTile,
     TileDataLow,
@@ -27,8 +43,43 @@ enum FetcherMode {
     Push,
 }
 
+#[derive(Clone, Copy, Default)]
+enum PaletteSource {
+    #[default]
+    Bgp,
+    Obp0,
+    Obp1,
+}
+
+impl From<PaletteSource> for LCDRegister {
+    fn from(val: PaletteSource) -> Self {
+        match val {
+            PaletteSource::Bgp => LCDRegister::Bgp,
+            PaletteSource::Obp0 => LCDRegister::Obp0,
+            PaletteSource::Obp1 => LCDRegister::Obp1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Pixel {
+    // 2 bit color index from the tile data, mapped to a shade by the palette register at output
+    color: u8,
+    palette: PaletteSource,
+    // Objects only, BG colors 1-3 are drawn over this pixel
+    bg_priority: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    tile: u8,
+    flags: u8,
+}
+
 struct Fifo {
-    pixels: VecDeque<u32>,
+    pixels: VecDeque<Pixel>,
     max_size: usize,
 }
 
@@ -40,16 +91,28 @@ impl Fifo {
         }
     }
 
-    pub fn push(&mut self, pixel: u32) {
+    pub fn push(&mut self, pixel: Pixel) {
         if self.pixels.len() < self.max_size {
-            self.pixels.push_front(pixel);
+            self.pixels.push_back(pixel);
         } else {
             panic!("PPU::Not sure if I should panic here::Fifo can't hold more pixels")
         }
     }
 
-    pub fn pop(&mut self) -> u32 {
-        self.pixels.pop_back().unwrap()
+    pub fn pop(&mut self) -> Pixel {
+        self.pixels.pop_front().unwrap()
+    }
+
+    // Objects only replace transparent pixels, so whichever sprite was mixed in first keeps priority
+    pub fn merge(&mut self, offset: usize, pixels: &[Pixel]) {
+        while self.pixels.len() < offset + pixels.len() {
+            self.pixels.push_back(Pixel::default());
+        }
+        for (i, pixel) in pixels.iter().enumerate() {
+            if self.pixels[offset + i].color == 0 {
+                self.pixels[offset + i] = *pixel;
+            }
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -75,13 +138,21 @@ pub struct Ppu<B: Bus> {
     hi_byte: u8,
     background_fifo: Fifo,
     object_fifo: Fifo,
+    sprites: Vec<Sprite>,
+    sprite_stall_cycles: usize,
+    window_active: bool,
+    window_line: u8,
+    wy_triggered: bool,
+    window_full_next_line: bool,
+    stat_line: bool,
+    lcd_off: bool,
     palette: Palette,
     pixels_to_discard: u8, // For fine scrolling mapped registers
 }
 
 impl<B: Bus> Ppu<B> {
     pub fn new(memory: Rc<RefCell<B>>, palette: Palette) -> Self {
-        memory.borrow_mut().write_u8(LCDRegister::Ly.into(), 0);
+        memory.borrow_mut().raw_write(LCDRegister::Ly.into(), 0);
         Self {
             memory,
             frame: FrameBuffer::new(SCREEN_WIDTH, SCREEN_HEIGHT),
@@ -96,6 +167,14 @@ impl<B: Bus> Ppu<B> {
             hi_byte: 0,
             background_fifo: Fifo::new(),
             object_fifo: Fifo::new(),
+            sprites: Vec::with_capacity(MAX_SPRITES_PER_LINE),
+            sprite_stall_cycles: 0,
+            window_active: false,
+            window_line: 0,
+            wy_triggered: false,
+            window_full_next_line: false,
+            stat_line: false,
+            lcd_off: false,
             palette,
             pixels_to_discard: 0,
         }
@@ -105,12 +184,56 @@ impl<B: Bus> Ppu<B> {
         self.palette = palette;
     }
 
+    // The ppu isn't affected by OAM DMA blocking the cpu, so it always accesses memory raw
     fn write_mem_u8(&self, addr: u16, value: u8) {
-        self.memory.borrow_mut().write_u8(addr, value);
+        self.memory.borrow_mut().raw_write(addr, value);
     }
 
     fn read_mem_u8(&self, addr: u16) -> u8 {
-        self.memory.borrow().read_u8(addr)
+        self.memory.borrow().raw_read(addr)
+    }
+
+    fn request_interrupt(&self, bit: u8) {
+        let mut interrupt_flag = self.read_mem_u8(0xFF0F);
+        interrupt_flag.set_bit(bit);
+        self.write_mem_u8(0xFF0F, interrupt_flag);
+    }
+
+    // Updates the mode and LY=LYC bits of STAT and requests the STAT interrupt. All enabled
+    // sources are ORed into one line and the interrupt only fires when that line goes high,
+    // so a new source becoming active while another is still active is blocked
+    fn update_stat(&mut self) {
+        let ly = self.read_mem_u8(LCDRegister::Ly.into());
+        let lyc = self.read_mem_u8(LCDRegister::Lyc.into());
+        let mut stat = self.read_mem_u8(LCDRegister::Stat.into()) & 0x78;
+
+        stat |= u8::from(self.mode);
+        if ly == lyc {
+            stat.set_bit(2);
+        }
+        self.write_mem_u8(LCDRegister::Stat.into(), stat);
+
+        let stat_line = (stat.get_bit(6) == 1 && ly == lyc)
+            || match self.mode {
+                PpuMode::HBlank => stat.get_bit(3) == 1,
+                PpuMode::VBlank => stat.get_bit(4) == 1,
+                PpuMode::OAMScan => stat.get_bit(5) == 1,
+                PpuMode::DrawingPixels => false,
+            };
+        if stat_line && !self.stat_line {
+            self.request_interrupt(1);
+        }
+        self.stat_line = stat_line;
+    }
+
+    fn set_mode(&mut self, mode: PpuMode) {
+        self.mode = mode;
+        self.update_stat();
+    }
+
+    fn set_ly(&mut self, ly: u8) {
+        self.write_mem_u8(LCDRegister::Ly.into(), ly);
+        self.update_stat();
     }
 
     fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
@@ -128,15 +251,29 @@ impl<B: Bus> Ppu<B> {
         self.frame.write(index, color);
     }
 
+    // Line within the background or window map the fetcher is currently drawing
+    fn fetcher_line(&self) -> u16 {
+        if self.window_active {
+            u16::from(self.window_line)
+        } else {
+            let ly = u16::from(self.read_mem_u8(LCDRegister::Ly.into()));
+            let scy = u16::from(self.read_mem_u8(LCDRegister::Scy.into()));
+            (ly + scy) & 0xFF
+        }
+    }
+
     fn get_tile_number(&mut self) -> u8 {
         let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into());
-        let ly = u16::from(self.read_mem_u8(LCDRegister::Ly.into()));
-        let scy = u16::from(self.read_mem_u8(LCDRegister::Scy.into()));
+        let line = self.fetcher_line();
 
-        let tile_map_base = u16::from((lcdc >> 3) & 1);
+        let tile_map_base = if self.window_active {
+            u16::from(lcdc.get_bit(6))
+        } else {
+            u16::from(lcdc.get_bit(3))
+        };
         let tile_num_addr = 0x9800
             | (tile_map_base << 10)
-            | ((((ly + scy) & 0xFF) >> 3) << 5)
+            | ((line >> 3) << 5)
             | (u16::from(self.fetcher_x) & 0x1F);
 
         self.read_mem_u8(tile_num_addr)
@@ -144,15 +281,14 @@ impl<B: Bus> Ppu<B> {
 
     fn get_tile_data_low(&mut self) -> u8 {
         let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into()) as u16;
-        let ly = self.read_mem_u8(LCDRegister::Ly.into()) as u16;
-        let scy = self.read_mem_u8(LCDRegister::Scy.into()) as u16;
+        let line = self.fetcher_line();
         let bit_12 = if !(((lcdc & 0x10) > 0) || (self.tile_number & 0x80) > 0) {
             1
         } else {
             0
         };
         self.tile_addr =
-            0x8000 | (bit_12 << 12) | (u16::from(self.tile_number) << 4) | (((ly + scy) % 8) << 1);
+            0x8000 | (bit_12 << 12) | (u16::from(self.tile_number) << 4) | ((line % 8) << 1);
         self.read_mem_u8(self.tile_addr)
     }
 
@@ -165,15 +301,11 @@ impl<B: Bus> Ppu<B> {
             let mask = 1 << bit;
             let lo = u16::from((self.lo_byte & mask) >> bit);
             let hi = u16::from((self.hi_byte & mask) >> bit);
-            let data: u8 = ((hi << 1) | lo) as u8;
-            let color: u32 = match data {
-                0 => self.palette.0,
-                1 => self.palette.1,
-                2 => self.palette.2,
-                3 => self.palette.3,
-                _ => panic!("Should not have any other color here"),
-            };
-            self.background_fifo.push(color);
+            let color: u8 = ((hi << 1) | lo) as u8;
+            self.background_fifo.push(Pixel {
+                color,
+                ..Default::default()
+            });
         }
         self.fetcher_x += 1;
         if self.fetcher_x >= 32 {
@@ -181,27 +313,260 @@ impl<B: Bus> Ppu<B> {
         }
     }
 
-    pub fn update_graphics(&mut self, cycles: usize) {
+    fn sprite_height(&self) -> u8 {
+        let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into());
+        if lcdc.get_bit(2) == 1 {
+            16
+        } else {
+            8
+        }
+    }
+
+    // Selects the first 10 objects in OAM that overlap the current line
+    fn scan_oam(&mut self) {
+        let ly = self.read_mem_u8(LCDRegister::Ly.into());
+        let height = self.sprite_height();
+        self.sprites.clear();
+        for i in 0..40 {
+            let addr = 0xFE00 + (i * 4);
+            let y = self.read_mem_u8(addr);
+            let line = u16::from(ly) + 16;
+            if line >= u16::from(y) && line < u16::from(y) + u16::from(height) {
+                self.sprites.push(Sprite {
+                    y,
+                    x: self.read_mem_u8(addr + 1),
+                    tile: self.read_mem_u8(addr + 2),
+                    flags: self.read_mem_u8(addr + 3),
+                });
+            }
+            if self.sprites.len() >= MAX_SPRITES_PER_LINE {
+                break;
+            }
+        }
+    }
+
+    // Finds the next sprite that starts at the current pixel. When several do, the one with the
+    // lowest X is fetched first, ties go to the sprite earliest in OAM
+    fn next_sprite(&self) -> Option<usize> {
+        let mut next: Option<usize> = None;
+        for (i, sprite) in self.sprites.iter().enumerate() {
+            if sprite.x > self.scanline_x + 8 {
+                continue;
+            }
+            match next {
+                Some(n) if self.sprites[n].x <= sprite.x => (),
+                _ => next = Some(i),
+            }
+        }
+        next
+    }
+
+    fn fetch_sprite(&mut self, sprite: Sprite) {
+        let ly = self.read_mem_u8(LCDRegister::Ly.into());
+        let height = self.sprite_height();
+
+        // LCDC.2 can change between the OAM scan and the fetch, 8x8 sprites then only use the low
+        // bits of a row picked for 8x16
+        let mut row = (ly + 16).wrapping_sub(sprite.y) & (height - 1);
+        if sprite.flags.get_bit(6) == 1 {
+            row = height - 1 - row;
+        }
+        let tile = if height == 16 {
+            (sprite.tile & 0xFE) + (row >> 3)
+        } else {
+            sprite.tile
+        };
+        let addr = 0x8000 | (u16::from(tile) << 4) | (u16::from(row & 7) << 1);
+        let lo_byte = self.read_mem_u8(addr);
+        let hi_byte = self.read_mem_u8(addr + 1);
+
+        let mut pixels = [Pixel::default(); 8];
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let bit = if sprite.flags.get_bit(5) == 1 {
+                i as u8
+            } else {
+                7 - i as u8
+            };
+            let lo = lo_byte.get_bit(bit);
+            let hi = hi_byte.get_bit(bit);
+            *pixel = Pixel {
+                color: (hi << 1) | lo,
+                palette: if sprite.flags.get_bit(4) == 1 {
+                    PaletteSource::Obp1
+                } else {
+                    PaletteSource::Obp0
+                },
+                bg_priority: sprite.flags.get_bit(7) == 1,
+            };
+        }
+
+        // Sprites partially off the left edge of the screen only have their visible pixels mixed in
+        let skipped = 8_usize.saturating_sub(usize::from(sprite.x));
+        if skipped < 8 {
+            self.object_fifo.merge(0, &pixels[skipped..]);
+        }
+    }
+
+    fn mix_pixel(&self, bg: Pixel, obj: Option<Pixel>) -> u32 {
+        let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into());
+        // On DMG clearing LCDC bit 0 blanks the background to white
+        let bg_enabled = lcdc.get_bit(0) == 1;
+        let bg_color = if bg_enabled { bg.color } else { 0 };
+
+        let pixel = match obj {
+            Some(obj)
+                if lcdc.get_bit(1) == 1
+                    && obj.color != 0
+                    && !(obj.bg_priority && bg_color != 0) =>
+            {
+                obj
+            }
+            _ if bg_enabled => bg,
+            _ => return self.shade_to_color(0),
+        };
+
+        let palette = self.read_mem_u8(LCDRegister::from(pixel.palette).into());
+        self.shade_to_color((palette >> (pixel.color * 2)) & 0b11)
+    }
+
+    fn shade_to_color(&self, shade: u8) -> u32 {
+        match shade {
+            0 => self.palette.0,
+            1 => self.palette.1,
+            2 => self.palette.2,
+            3 => self.palette.3,
+            _ => panic!("Should not have any other color here"),
+        }
+    }
+
+    // Checks if the window starts at the current pixel. WX < 7 starts the window at the left edge
+    // with the first 7 - WX pixels cut off
+    fn window_starts(&self) -> bool {
+        let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into());
+        if self.window_active || lcdc.get_bit(5) == 0 || !self.wy_triggered {
+            return false;
+        }
+
+        let wx = self.read_mem_u8(LCDRegister::Wx.into());
+        if self.window_full_next_line || wx < 7 {
+            self.scanline_x == 0
+        } else {
+            self.pixels_to_discard == 0 && self.scanline_x + 7 == wx
+        }
+    }
+
+    fn start_window(&mut self) {
+        let wx = self.read_mem_u8(LCDRegister::Wx.into());
+        let scx = self.read_mem_u8(LCDRegister::Scx.into());
+
+        self.pixels_to_discard = if self.window_full_next_line {
+            0
+        } else if wx == 0 && scx & 7 != 0 {
+            // With WX = 0 the window is shifted by the fine scroll instead, making it stutter as
+            // SCX changes
+            scx & 7
+        } else {
+            7_u8.saturating_sub(wx)
+        };
+
+        // On DMG a window started at WX = 166 covers the whole of the next line
+        self.window_full_next_line = wx == 166;
+
+        self.window_active = true;
+        self.fetcher_x = 0;
+        self.fetcher_mode = FetcherMode::GetTile;
+        self.background_fifo.clear();
+    }
+
+    // Resets the per line state ready for the next OAM scan
+    fn end_scanline(&mut self) {
+        // The window line counter only advances on lines where the window was actually drawn
+        if self.window_active {
+            self.window_line = self.window_line.wrapping_add(1);
+        } else {
+            self.window_full_next_line = false;
+        }
+        self.window_active = false;
+    }
+
+    // With the LCD off LY stays at 0 in mode 0, turning it back on starts a fresh frame
+    fn reset_lcd(&mut self) {
+        self.current_scanline_cycles = 0;
+        self.scanline_x = 0;
+        self.window_active = false;
+        self.window_line = 0;
+        self.wy_triggered = false;
+        self.window_full_next_line = false;
+        self.set_ly(0);
+        self.set_mode(PpuMode::HBlank);
+        self.lcd_off = true;
+    }
+
+    /// Runs the ppu for `cycles` T-cycles, returns whether VBlank started during them
+    pub fn update_graphics(&mut self, cycles: usize) -> bool {
         let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into());
         if lcdc.get_bit(7) == 0 {
-            return;
+            if !self.lcd_off {
+                self.reset_lcd();
+            }
+            return false;
         }
+        if self.lcd_off {
+            self.lcd_off = false;
+            self.mode = PpuMode::OAMScan;
+        }
+
+        // Catch LYC and STAT enable writes made since the last update
+        self.update_stat();
 
+        let mut vblank_started = false;
         for i in 0..cycles {
             self.current_scanline_cycles += 1;
             match self.mode {
                 PpuMode::OAMScan => {
                     if self.current_scanline_cycles >= 80 {
+                        self.scan_oam();
+
+                        let ly = self.read_mem_u8(LCDRegister::Ly.into());
+                        let wy = self.read_mem_u8(LCDRegister::Wy.into());
+                        if ly == wy {
+                            self.wy_triggered = true;
+                        }
+
                         // Initialize for drawing pixels
                         let scx = self.read_mem_u8(LCDRegister::Scx.into());
                         self.fetcher_x = scx >> 3;
                         self.pixels_to_discard = scx & 7;
                         self.background_fifo.clear();
+                        self.object_fifo.clear();
+                        self.sprite_stall_cycles = 0;
                         self.fetcher_mode = FetcherMode::GetTile;
-                        self.mode = PpuMode::DrawingPixels;
+                        self.set_mode(PpuMode::DrawingPixels);
                     }
                 }
                 PpuMode::DrawingPixels => {
+                    // Fetching a sprite pauses both the background fetcher and pixel output
+                    if self.sprite_stall_cycles > 0 {
+                        self.sprite_stall_cycles -= 1;
+                        continue;
+                    }
+
+                    if self.window_starts() {
+                        self.start_window();
+                    }
+
+                    if self.pixels_to_discard == 0 && self.background_fifo.len() > 0 {
+                        let lcdc = self.read_mem_u8(LCDRegister::Lcdc.into());
+                        if lcdc.get_bit(1) == 1 {
+                            if let Some(index) = self.next_sprite() {
+                                let sprite = self.sprites.remove(index);
+                                self.fetch_sprite(sprite);
+                                self.sprite_stall_cycles = SPRITE_FETCH_CYCLES - 1;
+                                continue;
+                            }
+                        }
+                    }
+
                     if i % 2 == 1 {
                         match self.fetcher_mode {
                             FetcherMode::GetTile => {
@@ -225,12 +590,18 @@ impl<B: Bus> Ppu<B> {
                     }
 
                     if self.background_fifo.len() > 0 {
-                        let color = self.background_fifo.pop();
+                        let bg = self.background_fifo.pop();
 
                         // Handle fine scrolling by discarding pixels
                         if self.pixels_to_discard > 0 {
                             self.pixels_to_discard -= 1;
                         } else {
+                            let obj = if self.object_fifo.len() > 0 {
+                                Some(self.object_fifo.pop())
+                            } else {
+                                None
+                            };
+                            let color = self.mix_pixel(bg, obj);
                             let ly = self.read_mem_u8(LCDRegister::Ly.into());
                             self.set_pixel(self.scanline_x as usize, ly as usize, color);
                             self.scanline_x += 1;
@@ -238,45 +609,133 @@ impl<B: Bus> Ppu<B> {
                     }
 
                     if self.scanline_x >= 160 {
-                        self.mode = PpuMode::HBlank;
+                        self.set_mode(PpuMode::HBlank);
                     }
                 }
                 PpuMode::HBlank => {
                     if self.current_scanline_cycles >= CYCLES_PER_SCANLINE {
+                        self.end_scanline();
                         let scx = self.read_mem_u8(LCDRegister::Scx.into());
                         self.scanline_x = 0;
                         self.fetcher_x = scx >> 3; // Start fetching from the correct tile
                         self.pixels_to_discard = scx & 7; // Fine scroll offset
                         self.background_fifo.clear(); // Clear FIFO for new scanline
+                        self.object_fifo.clear();
                         self.fetcher_mode = FetcherMode::GetTile; // Reset fetcher
                         self.current_scanline_cycles = 0;
                         let mut ly = self.read_mem_u8(LCDRegister::Ly.into());
                         ly = ly.wrapping_add(1);
-                        self.write_mem_u8(LCDRegister::Ly.into(), ly);
+                        self.set_ly(ly);
                         if ly >= 144 {
-                            self.mode = PpuMode::VBlank;
+                            self.set_mode(PpuMode::VBlank);
+                            self.request_interrupt(0);
+                            vblank_started = true;
                         } else {
-                            self.mode = PpuMode::OAMScan;
+                            self.set_mode(PpuMode::OAMScan);
                         }
                     }
                 }
                 PpuMode::VBlank => {
+                    let ly = self.read_mem_u8(LCDRegister::Ly.into());
+
+                    // LY only reads 153 for the first few cycles of the last line before
+                    // dropping to 0, so LYC=0 matches while still in VBlank
+                    if ly == 153 && self.current_scanline_cycles >= LINE_153_LY_RESET_CYCLES {
+                        self.set_ly(0);
+                    }
+
                     if self.current_scanline_cycles >= CYCLES_PER_SCANLINE {
                         self.current_scanline_cycles = 0;
-                        let mut ly = self.read_mem_u8(LCDRegister::Ly.into());
-                        ly = ly.wrapping_add(1);
-                        self.write_mem_u8(LCDRegister::Ly.into(), ly);
-                        if ly >= 153 {
-                            self.write_mem_u8(LCDRegister::Ly.into(), 0);
-                            self.mode = PpuMode::OAMScan;
+                        let ly = self.read_mem_u8(LCDRegister::Ly.into());
+                        if ly == 0 {
+                            self.window_line = 0;
+                            self.wy_triggered = false;
+                            self.set_mode(PpuMode::OAMScan);
+                        } else {
+                            self.set_ly(ly + 1);
                         }
                     }
                 }
             }
         }
+        vblank_started
     }
 
     pub fn get_frame(&self) -> &FrameBuffer {
         &self.frame
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::RawBus;
+
+    const PALETTE: Palette = (0xFFFFFF, 0xA9A9A9, 0x545454, 0x000000);
+
+    fn ppu() -> Ppu<RawBus> {
+        Ppu::new(Rc::new(RefCell::new(RawBus::new())), PALETTE)
+    }
+
+    // Draws a frame with a blank background and a window from 0x9C00 whose tiles only have
+    // their leftmost pixel set, so every window tile edge shows up as shade 1
+    fn window_frame(wx: u8, scx: u8) -> Ppu<RawBus> {
+        let mut ppu = ppu();
+        for addr in 0x9C00..0xA000 {
+            ppu.write_mem_u8(addr, 1);
+        }
+        for row in 0..8 {
+            ppu.write_mem_u8(0x8010 + row * 2, 0x80);
+        }
+        ppu.write_mem_u8(LCDRegister::Bgp.into(), 0xE4);
+        ppu.write_mem_u8(LCDRegister::Wx.into(), wx);
+        ppu.write_mem_u8(LCDRegister::Scx.into(), scx);
+        ppu.write_mem_u8(LCDRegister::Lcdc.into(), 0xF1);
+        ppu.update_graphics(CYCLES_PER_SCANLINE * SCREEN_HEIGHT);
+        ppu
+    }
+
+    fn window_edges(ppu: &Ppu<RawBus>, y: usize) -> Vec<usize> {
+        (0..SCREEN_WIDTH)
+            .filter(|x| ppu.get_frame().read(y * SCREEN_WIDTH + x) == PALETTE.1)
+            .collect()
+    }
+
+    #[test]
+    fn window_at_wx_0_is_shifted_by_fine_scroll() {
+        let ppu = window_frame(0, 0);
+        assert_eq!(window_edges(&ppu, 0)[..2], [1, 9]);
+
+        let ppu = window_frame(0, 3);
+        assert_eq!(window_edges(&ppu, 0)[..2], [5, 13]);
+    }
+
+    #[test]
+    fn window_at_wx_166_covers_the_next_line() {
+        let ppu = window_frame(166, 0);
+        assert_eq!(window_edges(&ppu, 0), [159]);
+        assert_eq!(window_edges(&ppu, 1)[..2], [0, 8]);
+        assert_eq!(window_edges(&ppu, 1).len(), 20);
+    }
+
+    #[test]
+    fn sprite_row_wraps_when_height_shrinks_after_oam_scan() {
+        let mut ppu = ppu();
+        ppu.write_mem_u8(LCDRegister::Lcdc.into(), 0x86);
+        ppu.write_mem_u8(LCDRegister::Ly.into(), 12);
+        // Y flipped 8x16 sprite whose 13th row is on this line
+        ppu.write_mem_u8(0xFE00, 16);
+        ppu.write_mem_u8(0xFE01, 8);
+        ppu.write_mem_u8(0xFE02, 2);
+        ppu.write_mem_u8(0xFE03, 0x40);
+        // Row 3 of tile 2, the flipped 5th row of an 8x8 sprite
+        ppu.write_mem_u8(0x8026, 0xFF);
+        ppu.scan_oam();
+        assert_eq!(ppu.sprites.len(), 1);
+
+        ppu.write_mem_u8(LCDRegister::Lcdc.into(), 0x82);
+        ppu.fetch_sprite(ppu.sprites[0]);
+        assert_eq!(ppu.object_fifo.len(), 8);
+        assert_eq!(ppu.object_fifo.pop().color, 1);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{emulator::CPU_FREQ, utils::bit_ops::BitOps};
+
+// The internal clock shifts one bit at 8192Hz
+const CYCLES_PER_BIT: usize = CPU_FREQ / 8192;
+const TRANSFER_CYCLES: usize = CYCLES_PER_BIT * 8;
+
+/// Whatever is plugged into the other end of the link cable
+pub trait SerialDevice {
+    /// Called when a transfer on the internal clock finishes, returns the byte shifted in
+    fn exchange(&mut self, outgoing: u8) -> u8;
+
+    /// Polled while the Game Boy waits on an external clock, returns the byte shifted in once
+    /// the device has clocked a transfer
+    fn external_clock(&mut self, _outgoing: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Nothing connected, every bit shifted in reads as 1 and the external clock never ticks
+pub struct Disconnected;
+
+impl SerialDevice for Disconnected {
+    fn exchange(&mut self, _outgoing: u8) -> u8 {
+        0xFF
+    }
+}
+
+pub struct Serial {
+    data: u8,
+    // SC bit 7 starts a transfer, bit 0 selects the internal clock
+    control: u8,
+    cycles_left: usize,
+    device: Box<dyn SerialDevice>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            cycles_left: 0,
+            device: Box::new(Disconnected),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = device;
+    }
+
+    /// Clears the registers, the connected device stays plugged in
+    pub fn reset(&mut self) {
+        self.data = 0;
+        self.control = 0;
+        self.cycles_left = 0;
+    }
+
+    fn transferring(&self) -> bool {
+        self.control.get_bit(7) == 1
+    }
+
+    fn internal_clock(&self) -> bool {
+        self.control.get_bit(0) == 1
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.data,
+            0xFF02 => self.control | 0x7E,
+            _ => unreachable!("Address {addr:#06x} is not a serial register"),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                self.control = value & 0x81;
+                self.cycles_left = TRANSFER_CYCLES;
+            }
+            _ => unreachable!("Address {addr:#06x} is not a serial register"),
+        }
+    }
+
+    fn finish_transfer(&mut self, incoming: u8) {
+        self.data = incoming;
+        self.control.clear_bit(7);
+    }
+
+    /// Advances a running transfer by `cycles` T-cycles, returns true if a serial interrupt was
+    /// requested
+    pub fn tick(&mut self, cycles: usize) -> bool {
+        if !self.transferring() {
+            return false;
+        }
+
+        if self.internal_clock() {
+            if self.cycles_left > cycles {
+                self.cycles_left -= cycles;
+                return false;
+            }
+            let incoming = self.device.exchange(self.data);
+            self.finish_transfer(incoming);
+            return true;
+        }
+
+        match self.device.external_clock(self.data) {
+            Some(incoming) => {
+                self.finish_transfer(incoming);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl SerialDevice for Echo {
+        fn exchange(&mut self, outgoing: u8) -> u8 {
+            outgoing.wrapping_add(1)
+        }
+    }
+
+    #[test]
+    fn internal_transfer_takes_eight_bits() {
+        let mut serial = Serial::new();
+        serial.set_device(Box::new(Echo));
+        serial.write(0xFF01, 0x41);
+        serial.write(0xFF02, 0x81);
+
+        assert!(!serial.tick(TRANSFER_CYCLES - 4));
+        assert_eq!(serial.read(0xFF02), 0xFF);
+        assert!(serial.tick(4));
+        assert_eq!(serial.read(0xFF01), 0x42);
+        assert_eq!(serial.read(0xFF02), 0x7F);
+    }
+
+    #[test]
+    fn external_clock_waits_for_device() {
+        let mut serial = Serial::new();
+        serial.write(0xFF01, 0x41);
+        serial.write(0xFF02, 0x80);
+
+        assert!(!serial.tick(TRANSFER_CYCLES * 2));
+        assert_eq!(serial.read(0xFF02), 0xFE);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::utils::bit_ops::BitOps;
+
+// Cycles between TIMA overflowing and it being reloaded from TMA
+const TIMA_RELOAD_DELAY: usize = 4;
+
+pub struct Timer {
+    // DIV is the upper 8 bits of this counter
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    reload_delay: usize,
+    last_signal: bool,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload_delay: 0,
+            last_signal: false,
+        }
+    }
+
+    // The counter bit TIMA watches for a falling edge, selected by TAC
+    fn selected_bit(&self) -> u16 {
+        match self.tac & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            0b11 => 7,
+            _ => unreachable!(),
+        }
+    }
+
+    fn signal(&self) -> bool {
+        self.tac.get_bit(2) == 1 && (self.counter >> self.selected_bit()) & 1 == 1
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload_delay = TIMA_RELOAD_DELAY;
+        }
+    }
+
+    // Any change to the counter or TAC can cause a falling edge, not just ticking
+    fn detect_falling_edge(&mut self) {
+        let signal = self.signal();
+        if self.last_signal && !signal {
+            self.increment_tima();
+        }
+        self.last_signal = signal;
+    }
+
+    /// Bit 4 of DIV clocks the APU frame sequencer on its falling edge
+    pub fn apu_signal(&self) -> bool {
+        self.counter & (1 << 12) != 0
+    }
+
+    /// Advances the timer by `cycles` T-cycles, returns true if a timer interrupt was requested
+    pub fn tick(&mut self, cycles: usize) -> bool {
+        let mut interrupt = false;
+        for _ in 0..cycles {
+            if self.reload_delay > 0 {
+                self.reload_delay -= 1;
+                if self.reload_delay == 0 {
+                    self.tima = self.tma;
+                    interrupt = true;
+                }
+            }
+
+            self.counter = self.counter.wrapping_add(1);
+            self.detect_falling_edge();
+        }
+        interrupt
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF04 => (self.counter >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            0xFF07 => self.tac | 0xF8,
+            _ => unreachable!("Address {addr:#06x} is not a timer register"),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF04 => {
+                // Resetting the counter can drop the selected bit and tick TIMA early
+                self.counter = 0;
+                self.detect_falling_edge();
+            }
+            0xFF05 => {
+                // Writing TIMA during the reload delay cancels the reload
+                self.reload_delay = 0;
+                self.tima = value;
+            }
+            0xFF06 => self.tma = value,
+            0xFF07 => {
+                self.tac = value & 0x07;
+                self.detect_falling_edge();
+            }
+            _ => unreachable!("Address {addr:#06x} is not a timer register"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::TimerRegister;
+
+    #[test]
+    fn tima_overflow_reloads_after_delay() {
+        let mut timer = Timer::new();
+        timer.write(TimerRegister::Tac.into(), 0b101); // enabled, every 16 cycles
+        timer.write(TimerRegister::Tma.into(), 0x42);
+        timer.write(TimerRegister::Tima.into(), 0xFF);
+
+        assert!(!timer.tick(16));
+        assert_eq!(timer.read(TimerRegister::Tima.into()), 0x00);
+        assert!(timer.tick(TIMA_RELOAD_DELAY));
+        assert_eq!(timer.read(TimerRegister::Tima.into()), 0x42);
+    }
+
+    #[test]
+    fn div_write_causes_falling_edge() {
+        let mut timer = Timer::new();
+        timer.write(TimerRegister::Tac.into(), 0b101);
+        timer.tick(8); // bit 3 is now set
+        timer.write(TimerRegister::Div.into(), 0xAB);
+        assert_eq!(timer.read(TimerRegister::Div.into()), 0x00);
+        assert_eq!(timer.read(TimerRegister::Tima.into()), 0x01);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::emulator::{cheats::CheatEffect, DMGBus, Emulator};
+
+pub struct CheatPanel {
+    code: String,
+    // Why the last code entered was rejected
+    error: Option<String>,
+}
+
+impl CheatPanel {
+    pub fn new() -> Self {
+        Self {
+            code: String::new(),
+            error: None,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator<DMGBus>) {
+        ui.horizontal(|ui| {
+            ui.label("Code");
+            ui.text_edit_singleline(&mut self.code);
+            if ui.button("Add").clicked() {
+                match emulator.add_cheat(&self.code) {
+                    Ok(_) => {
+                        self.code.clear();
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        ui.separator();
+
+        let mut removed = None;
+        egui::Grid::new("cheats").show(ui, |ui| {
+            for (index, cheat) in emulator.cheats().into_iter().enumerate() {
+                let mut enabled = cheat.enabled;
+                if ui.checkbox(&mut enabled, &cheat.code).changed() {
+                    emulator.set_cheat_enabled(index, enabled);
+                }
+                let description = match cheat.effect {
+                    CheatEffect::RomPatch {
+                        address,
+                        value,
+                        compare: Some(compare),
+                    } => format!("{address:04X}: {compare:02X} -> {value:02X}"),
+                    CheatEffect::RomPatch { address, value, .. } => {
+                        format!("{address:04X} -> {value:02X}")
+                    }
+                    CheatEffect::RamWrite {
+                        bank: Some(bank),
+                        address,
+                        value,
+                    } => format!("{address:04X} bank {bank} = {value:02X}"),
+                    CheatEffect::RamWrite { address, value, .. } => {
+                        format!("{address:04X} = {value:02X}")
+                    }
+                };
+                ui.monospace(description);
+                if ui.button("Remove").clicked() {
+                    removed = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = removed {
+            emulator.remove_cheat(index);
+        }
+    }
+}
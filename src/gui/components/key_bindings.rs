@@ -0,0 +1,125 @@
+use std::{collections::HashMap, fs};
+
+use egui::{Context, Event, Key};
+
+use crate::emulator::Button;
+
+const KEY_BINDINGS_PATH: &str = "./key_bindings.json";
+
+pub struct KeyBindings {
+    bindings: Vec<(Button, Key)>,
+    rebinding: Option<Button>,
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        Self {
+            bindings: vec![
+                (Button::Right, Key::ArrowRight),
+                (Button::Left, Key::ArrowLeft),
+                (Button::Up, Key::ArrowUp),
+                (Button::Down, Key::ArrowDown),
+                (Button::A, Key::X),
+                (Button::B, Key::Z),
+                (Button::Select, Key::Backspace),
+                (Button::Start, Key::Enter),
+            ],
+            rebinding: None,
+        }
+    }
+
+    /// Loads the saved bindings, any button missing from the file keeps its default key
+    pub fn load() -> Self {
+        let mut key_bindings = Self::new();
+        let Ok(data) = fs::read_to_string(KEY_BINDINGS_PATH) else {
+            return key_bindings;
+        };
+
+        let saved: HashMap<String, String> = match serde_json::from_str(&data) {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("Unable to parse {KEY_BINDINGS_PATH}: {e}");
+                return key_bindings;
+            }
+        };
+
+        for (button, key) in key_bindings.bindings.iter_mut() {
+            if let Some(saved_key) = saved
+                .get(&button.to_string())
+                .and_then(|k| Key::from_name(k))
+            {
+                *key = saved_key;
+            }
+        }
+        key_bindings
+    }
+
+    fn save(&self) {
+        let saved: HashMap<String, String> = self
+            .bindings
+            .iter()
+            .map(|(button, key)| (button.to_string(), key.name().to_string()))
+            .collect();
+        let data = serde_json::to_string_pretty(&saved).expect("Unable to serialize key bindings");
+        if let Err(e) = fs::write(KEY_BINDINGS_PATH, data) {
+            eprintln!("Unable to save key bindings to {KEY_BINDINGS_PATH}: {e}");
+        }
+    }
+
+    fn bind(&mut self, button: Button, key: Key) {
+        for (b, k) in self.bindings.iter_mut() {
+            if *b == button {
+                *k = key;
+            }
+        }
+        self.save();
+    }
+
+    /// Returns the state of every button based on which keys are currently held
+    pub fn button_states(&self, ctx: &Context) -> Vec<(Button, bool)> {
+        if self.rebinding.is_some() {
+            return Button::ALL.iter().map(|b| (*b, false)).collect();
+        }
+
+        ctx.input(|i| {
+            self.bindings
+                .iter()
+                .map(|(button, key)| (*button, i.key_down(*key)))
+                .collect()
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(button) = self.rebinding {
+            let pressed = ui.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            if let Some(key) = pressed {
+                if key != Key::Escape {
+                    self.bind(button, key);
+                }
+                self.rebinding = None;
+            }
+        }
+
+        egui::Grid::new("key_bindings").show(ui, |ui| {
+            for (button, key) in self.bindings.clone() {
+                ui.label(button.to_string());
+                if self.rebinding == Some(button) {
+                    ui.label("Press a key... (Esc to cancel)");
+                } else {
+                    ui.label(key.name());
+                }
+                if ui.button("Rebind").clicked() {
+                    self.rebinding = Some(button);
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
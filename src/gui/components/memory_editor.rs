@@ -1,5 +1,3 @@
-use std::mem;
-
 pub struct MemoryEditor {
     bytes_per_row: usize,
     frame_size: usize,
@@ -68,7 +66,11 @@ impl MemoryEditor {
                             .margin(0.0)
                             .char_limit(2)
                             .frame(false);
-                        ui.add(edit_box);
+                        if ui.add(edit_box).changed() {
+                            if let Ok(value) = u8::from_str_radix(&byte_str, 16) {
+                                write(addr as u16, value);
+                            }
+                        }
                     }
                 });
             }
@@ -1,2 +1,4 @@
+pub mod cheats;
 pub mod emu_screen;
+pub mod key_bindings;
 pub mod memory_editor;
@@ -1,14 +1,21 @@
 mod components;
-use std::cell::RefCell;
+#[cfg(feature = "audio")]
+mod playback;
+use std::{cell::RefCell, error::Error, path::Path};
 
 use eframe::Frame;
 use egui::Context;
 
-use crate::emulator::cartridge::Cartridge;
+use crate::emulator::audio::wav::WavSink;
+use crate::emulator::cartridge::{Cartridge, RtcClock};
 use crate::emulator::DMGBus;
 use crate::emulator::{Emulator, RunType, SCREEN_HEIGHT, SCREEN_WIDTH};
-use crate::gui::components::{emu_screen::EmuScreen, memory_editor::MemoryEditor};
-use crate::Palette;
+use crate::gui::components::{
+    cheats::CheatPanel, emu_screen::EmuScreen, key_bindings::KeyBindings,
+    memory_editor::MemoryEditor,
+};
+
+const RECORDING_SAMPLE_RATE: u32 = 44_100;
 
 pub struct EmulatorGui {
     emulator: Emulator<DMGBus>,
@@ -18,15 +25,35 @@ pub struct EmulatorGui {
     background_map: EmuScreen,
     background_map_shown: bool,
     memory_editor: MemoryEditor,
+    key_bindings: KeyBindings,
+    key_bindings_shown: bool,
+    cheat_panel: CheatPanel,
+    cheats_shown: bool,
     run_type: RunType,
     show_debug_screen: bool,
+    rom_info_shown: bool,
+    // Shown in a window until dismissed when a rom fails to load
+    load_error: Option<String>,
+    // Audio goes to a WAV file instead of host playback while recording
+    recording: bool,
+    #[cfg(feature = "audio")]
+    playback: Option<playback::Playback>,
 }
 
 impl EmulatorGui {
     pub fn new(emulator: Emulator<DMGBus>) -> Self {
         let run_type = emulator.run_type();
         let memory_editor = MemoryEditor::new(16, 0x10000, 0x100);
-        Self {
+        #[cfg(feature = "audio")]
+        let playback = match playback::Playback::new() {
+            Ok(playback) => Some(playback),
+            Err(e) => {
+                eprintln!("Unable to start audio playback: {e}");
+                None
+            }
+        };
+
+        let mut gui = Self {
             emulator,
             emu_screen: EmuScreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
             tile_map: EmuScreen::new(128, 192),
@@ -34,14 +61,81 @@ impl EmulatorGui {
             background_map: EmuScreen::new(32 * 8, 32 * 8),
             background_map_shown: false,
             memory_editor,
+            key_bindings: KeyBindings::load(),
+            key_bindings_shown: false,
+            cheat_panel: CheatPanel::new(),
+            cheats_shown: false,
             run_type,
             show_debug_screen: false,
+            rom_info_shown: false,
+            load_error: None,
+            recording: false,
+            #[cfg(feature = "audio")]
+            playback,
+        };
+        gui.attach_audio();
+        gui
+    }
+
+    // Swaps in a fresh emulator running the rom, the current one keeps running if it fails. A
+    // patch given here is used instead of one found next to the rom
+    fn load_rom(&mut self, path: &Path, patch: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        // Flushed first in case the same rom is being reloaded
+        if let Err(e) = self.emulator.save() {
+            eprintln!("Unable to write save file: {e}");
+        }
+        let cartridge = match patch {
+            Some(patch) => {
+                Cartridge::from_patched(&path.to_string_lossy(), &patch.to_string_lossy())?
+            }
+            None => Cartridge::from(&path.to_string_lossy())?,
+        }
+        .with_rtc_clock(RtcClock::WallClock);
+        let flags = self.emulator.debug_ctx().get_flags();
+        let emulator = Emulator::<DMGBus>::new()
+            .with_debug_flags(flags)
+            .with_rom(cartridge)?;
+        // Dropping the old emulator finishes any recording
+        self.emulator = emulator;
+        self.recording = false;
+        self.attach_audio();
+        self.emulator.set_run_type(self.run_type);
+        Ok(())
+    }
+
+    fn start_recording(&mut self, path: &Path) {
+        match WavSink::create(path, RECORDING_SAMPLE_RATE) {
+            Ok(sink) => {
+                self.emulator.set_audio_sink(Box::new(sink));
+                self.recording = true;
+            }
+            Err(e) => eprintln!("Unable to record audio to '{}': {e}", path.display()),
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        self.emulator.clear_audio_sink();
+        self.recording = false;
+        self.attach_audio();
+    }
+
+    #[cfg(feature = "audio")]
+    fn attach_audio(&mut self) {
+        if let Some(playback) = &self.playback {
+            self.emulator.set_audio_sink(playback.sink());
         }
     }
+
+    #[cfg(not(feature = "audio"))]
+    fn attach_audio(&mut self) {}
 }
 
 impl eframe::App for EmulatorGui {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+        for (button, pressed) in self.key_bindings.button_states(ctx) {
+            self.emulator.set_button(button, pressed);
+        }
+
         self.emu_screen
             .update_texture(&self.emulator.tick().unwrap().rgb(), ctx);
 
@@ -66,22 +160,60 @@ impl eframe::App for EmulatorGui {
                 });
                 ui.menu_button("File", |ui| {
                     if ui.button("Select Rom...").clicked() {
+                        let path = rfd::FileDialog::new().set_directory("~").pick_file();
+                        if let Some(path) = path {
+                            if let Err(e) = self.load_rom(&path, None) {
+                                self.load_error = Some(format!("{}: {e}", path.display()));
+                            }
+                        }
+                    }
+                    if ui.button("Select Rom with Patch...").clicked() {
+                        let path = rfd::FileDialog::new().set_directory("~").pick_file();
+                        let patch = path.as_ref().and_then(|path| {
+                            rfd::FileDialog::new()
+                                .set_title("Select Patch")
+                                .add_filter("IPS, UPS or BPS patch", &["ips", "ups", "bps"])
+                                .set_directory(path.parent().unwrap_or(Path::new("~")))
+                                .pick_file()
+                        });
+                        if let (Some(path), Some(patch)) = (path, patch) {
+                            if let Err(e) = self.load_rom(&path, Some(&patch)) {
+                                self.load_error = Some(format!("{}: {e}", path.display()));
+                            }
+                        }
+                    }
+                    if ui.button("Rom Info").clicked() {
+                        self.rom_info_shown = !self.rom_info_shown;
+                    }
+                    if ui.button("Cheats").clicked() {
+                        self.cheats_shown = !self.cheats_shown;
+                    }
+                    if self.recording {
+                        if ui.button("Stop Recording").clicked() {
+                            self.stop_recording();
+                        }
+                    } else if ui.button("Record Audio...").clicked() {
                         let path = rfd::FileDialog::new()
-                            .set_directory("~")
-                            .pick_file()
-                            .unwrap();
-                        let cartridge = Cartridge::from(path.to_str().unwrap()).unwrap();
-                        let flags = self.emulator.debug_ctx().get_flags();
-                        self.emulator = Emulator::<DMGBus>::new()
-                            .with_debug_flags(flags)
-                            .with_rom(cartridge)
-                            .unwrap();
-                        self.emulator.set_run_type(self.run_type);
+                            .add_filter("WAV audio", &["wav"])
+                            .set_file_name("recording.wav")
+                            .save_file();
+                        if let Some(path) = path {
+                            self.start_recording(&path);
+                        }
                     }
                     if ui.button("Dump Memory").clicked() {
                         self.emulator.debug_ctx_mut().dump_logs();
                     }
                 });
+                ui.menu_button("Settings", |ui| {
+                    if ui.button("Key Bindings").clicked() {
+                        self.key_bindings_shown = !self.key_bindings_shown;
+                    }
+                });
+                // Stands in for the motor of rumble cartridges
+                if self.emulator.rumbling() {
+                    ui.label("Rumble");
+                }
             });
             ui.separator();
             ui.horizontal(|ui| {
@@ -141,6 +273,44 @@ impl eframe::App for EmulatorGui {
                 }
             });
         }
+        if self.rom_info_shown {
+            let info = match self.emulator.cartridge_header() {
+                Some(header) => header.to_string(),
+                None => "No rom loaded".to_string(),
+            };
+            egui::Window::new("Rom Info").show(ctx, |ui| {
+                ui.monospace(info);
+                if ui.button("Close").clicked() {
+                    self.rom_info_shown = false;
+                }
+            });
+        }
+        if let Some(error) = &self.load_error {
+            let mut dismissed = false;
+            egui::Window::new("Unable to load rom").show(ctx, |ui| {
+                ui.label(error);
+                dismissed = ui.button("Close").clicked();
+            });
+            if dismissed {
+                self.load_error = None;
+            }
+        }
+        if self.key_bindings_shown {
+            egui::Window::new("Key Bindings").show(ctx, |ui| {
+                self.key_bindings.ui(ui);
+                if ui.button("Close").clicked() {
+                    self.key_bindings_shown = false;
+                }
+            });
+        }
+        if self.cheats_shown {
+            egui::Window::new("Cheats").show(ctx, |ui| {
+                self.cheat_panel.ui(ui, &mut self.emulator);
+                if ui.button("Close").clicked() {
+                    self.cheats_shown = false;
+                }
+            });
+        }
         ctx.request_repaint();
     }
 }
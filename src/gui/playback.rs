@@ -0,0 +1,99 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    SampleFormat, Stream,
+};
+
+use crate::emulator::audio::AudioSink;
+
+// Roughly 100ms of audio, anything past this is dropped so playback can't lag behind
+const MAX_QUEUED_SECONDS: f32 = 0.1;
+
+type SampleQueue = Arc<Mutex<VecDeque<(f32, f32)>>>;
+
+/// Owns the host output stream, emulators get a `PlaybackSink` feeding it
+pub struct Playback {
+    _stream: Stream,
+    queue: SampleQueue,
+    sample_rate: u32,
+}
+
+impl Playback {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No audio output device found")?;
+        let config = device.default_output_config()?;
+        if config.sample_format() != SampleFormat::F32 {
+            return Err(format!("Unsupported sample format {}", config.sample_format()).into());
+        }
+
+        let config: cpal::StreamConfig = config.into();
+        let channels = config.channels as usize;
+        let queue: SampleQueue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream_queue = Arc::clone(&queue);
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], _| {
+                let mut queue = stream_queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    // Underruns play silence
+                    let (left, right) = queue.pop_front().unwrap_or((0.0, 0.0));
+                    match frame {
+                        // Mono devices get both terminals mixed down
+                        [mono] => *mono = (left + right) / 2.0,
+                        _ => {
+                            for (channel, sample) in frame.iter_mut().enumerate() {
+                                *sample = if channel % 2 == 0 { left } else { right };
+                            }
+                        }
+                    }
+                }
+            },
+            |e| eprintln!("Audio stream error: {e}"),
+            None,
+        )?;
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            queue,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    pub fn sink(&self) -> Box<dyn AudioSink> {
+        Box::new(PlaybackSink {
+            queue: Arc::clone(&self.queue),
+            sample_rate: self.sample_rate,
+        })
+    }
+}
+
+pub struct PlaybackSink {
+    queue: SampleQueue,
+    sample_rate: u32,
+}
+
+impl AudioSink for PlaybackSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, samples: &[(f32, f32)]) -> Result<(), Box<dyn Error>> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let max_queued = (self.sample_rate as f32 * MAX_QUEUED_SECONDS) as usize;
+        if queue.len() > max_queued {
+            let excess = queue.len() - max_queued;
+            queue.drain(..excess);
+        }
+        Ok(())
+    }
+}
@@ -7,7 +7,6 @@
 * Add object drawing
 * Add interrupts
 * Add RAM bank switching
-* Implement timer
 * Move json tests to debugger
 */
 
@@ -19,13 +18,20 @@
 
 mod emulator;
 mod gui;
+#[cfg(test)]
+mod runner;
+#[cfg(test)]
+mod screenshot;
 mod utils;
 
 use crate::gui::EmulatorGui;
-use std::error::Error;
+use std::{env, error::Error, fs};
 
 use crate::emulator::debug::DebugFlag;
-use emulator::{cartridge::Cartridge, DMGBus, Emulator};
+use emulator::{
+    cartridge::{Cartridge, CartridgeHeader, RtcClock},
+    DMGBus, Emulator,
+};
 
 type Color = u32;
 type Palette = (Color, Color, Color, Color);
@@ -33,14 +39,29 @@ type Palette = (Color, Color, Color, Color);
 const GREEN_PALETTE: Palette = (0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F);
 const GRAY_PALETTE: Palette = (0xFFFFFF, 0xa9a9a9, 0x545454, 0x000000);
 
+// `gameboy-emulator info <rom>` prints the cartridge header without starting the emulator
+fn print_rom_info(rom_path: &str) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(rom_path)?;
+    let header = CartridgeHeader::parse(&rom)?;
+    println!("{header}");
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let dmg_acid2 = Cartridge::from("./roms/tests/dmg-acid2.gb")?;
-    let dr_mario = Cartridge::from("./roms/games/Dr. Mario (World).gb")?;
+    let args: Vec<String> = env::args().collect();
+    if let [_, command, rom_path] = args.as_slice() {
+        if command == "info" {
+            return print_rom_info(rom_path);
+        }
+    }
+
+    let dmg_acid2 =
+        Cartridge::from("./roms/tests/dmg-acid2.gb")?.with_rtc_clock(RtcClock::WallClock);
 
     let mut emulator = Emulator::<DMGBus>::new()
         .with_debug_flags(vec![DebugFlag::DumpCallLog, DebugFlag::DumpMem])
         .with_palette(GREEN_PALETTE)
-        .with_rom(dr_mario)?;
+        .with_rom(dmg_acid2)?;
 
     emulator.run();
 
@@ -70,4 +91,56 @@ mod tests {
         let mut emulator = Emulator::<RawBus>::new();
         assert!(emulator.run_opcode_tests().expect("Failed to run tests"));
     }
+
+    // Records a few seconds of every game so CI can diff the audio against earlier runs
+    #[test]
+    fn record_game_audio() {
+        use crate::emulator::audio::wav::WavSink;
+        use std::fs;
+
+        let output_dir = "./target/audio";
+        fs::create_dir_all(output_dir).unwrap();
+        for entry in fs::read_dir("./roms/games").unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let wav_path = format!("{output_dir}/{name}.wav");
+
+            let sink = WavSink::create(&wav_path, 44_100).unwrap();
+            let mut emulator = Emulator::<DMGBus>::new()
+                .with_audio_sink(Box::new(sink))
+                .with_rom(Cartridge::from(path.to_str().unwrap()).unwrap())
+                .unwrap();
+            emulator.run();
+            for _ in 0..300 {
+                emulator.tick().unwrap();
+            }
+            drop(emulator);
+
+            // 44 byte header followed by 4 bytes per stereo sample
+            let wav = fs::read(&wav_path).unwrap();
+            assert_eq!(&wav[0..4], b"RIFF");
+            assert_eq!(&wav[8..12], b"WAVE");
+            assert!(wav.len() > 44 + 4 * 44_100 * 4, "{wav_path} is too short");
+
+            // Both games make sound on both terminals within those seconds
+            let (left, right): (Vec<i16>, Vec<i16>) = wav[44..]
+                .chunks_exact(4)
+                .map(|frame| {
+                    (
+                        i16::from_le_bytes([frame[0], frame[1]]),
+                        i16::from_le_bytes([frame[2], frame[3]]),
+                    )
+                })
+                .unzip();
+            let audible = |samples: &[i16]| samples.iter().filter(|s| s.abs() > 256).count();
+            assert!(
+                audible(&left) > 44_100 / 10,
+                "{wav_path} left channel is silent"
+            );
+            assert!(
+                audible(&right) > 44_100 / 10,
+                "{wav_path} right channel is silent"
+            );
+        }
+    }
 }
@@ -0,0 +1,224 @@
+use std::{cell::RefCell, error::Error, fmt, path::Path, rc::Rc};
+
+use crate::emulator::{cartridge::Cartridge, serial::SerialDevice, DMGBus, Emulator};
+
+// Roughly a minute of emulated time, the slowest Blargg ROMs finish well within this
+const DEFAULT_CYCLE_BUDGET: usize = 4_194_304 * 60;
+const CYCLES_PER_FRAME: usize = 70_224;
+
+// Blargg's test framework mirrors its output to cartridge RAM once these bytes are written
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const SIGNATURE_ADDR: u16 = 0xA001;
+const STATUS_ADDR: u16 = 0xA000;
+const TEXT_ADDR: u16 = 0xA004;
+const STATUS_RUNNING: u8 = 0x80;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    TimedOut,
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOutcome::Passed => write!(f, "Passed"),
+            TestOutcome::Failed => write!(f, "Failed"),
+            TestOutcome::TimedOut => write!(f, "Timed out"),
+        }
+    }
+}
+
+pub struct TestReport {
+    pub rom: String,
+    pub outcome: TestOutcome,
+    pub output: String,
+    pub cycles: usize,
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} after {} cycles",
+            self.rom, self.outcome, self.cycles
+        )?;
+        if self.outcome != TestOutcome::Passed {
+            write!(f, "\n{}", self.output.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+// Collects every byte the ROM sends over the link cable
+struct SerialCapture {
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SerialDevice for SerialCapture {
+    fn exchange(&mut self, outgoing: u8) -> u8 {
+        self.output.borrow_mut().push(outgoing);
+        0xFF
+    }
+}
+
+/// Runs a test ROM without the GUI until it reports a result or runs out of cycles
+pub struct TestRunner {
+    rom: String,
+    emulator: Emulator<DMGBus>,
+    serial: Rc<RefCell<Vec<u8>>>,
+    cycle_budget: usize,
+}
+
+impl TestRunner {
+    pub fn new(rom_path: &str) -> Result<Self, Box<dyn Error>> {
+        let serial = Rc::new(RefCell::new(Vec::new()));
+        let capture = SerialCapture {
+            output: Rc::clone(&serial),
+        };
+        let mut emulator = Emulator::<DMGBus>::new()
+            .with_serial_device(Box::new(capture))
+            .with_rom(Cartridge::from(rom_path)?.with_save_path(None)?)?;
+        emulator.run();
+
+        let rom = Path::new(rom_path)
+            .file_name()
+            .map_or(rom_path.to_string(), |name| {
+                name.to_string_lossy().to_string()
+            });
+
+        Ok(Self {
+            rom,
+            emulator,
+            serial,
+            cycle_budget: DEFAULT_CYCLE_BUDGET,
+        })
+    }
+
+    fn serial_text(&self) -> String {
+        String::from_utf8_lossy(&self.serial.borrow()).to_string()
+    }
+
+    // Some ROMs only report through cartridge RAM, the status byte is valid once signed
+    fn memory_result(&self) -> Option<(u8, String)> {
+        let debug = self.emulator.debug_ctx();
+        let signed = SIGNATURE
+            .iter()
+            .zip(SIGNATURE_ADDR..)
+            .all(|(byte, addr)| debug.raw_read(addr) == *byte);
+        if !signed {
+            return None;
+        }
+
+        let text: Vec<u8> = (TEXT_ADDR..0xC000)
+            .map(|addr| debug.raw_read(addr))
+            .take_while(|byte| *byte != 0)
+            .collect();
+        Some((
+            debug.raw_read(STATUS_ADDR),
+            String::from_utf8_lossy(&text).to_string(),
+        ))
+    }
+
+    fn outcome(&self) -> Option<(TestOutcome, String)> {
+        let serial = self.serial_text();
+        if serial.contains("Passed") {
+            return Some((TestOutcome::Passed, serial));
+        }
+        if serial.contains("Failed") {
+            return Some((TestOutcome::Failed, serial));
+        }
+
+        match self.memory_result() {
+            Some((STATUS_RUNNING, _)) | None => None,
+            Some((0, text)) => Some((TestOutcome::Passed, text)),
+            Some((_, text)) => Some((TestOutcome::Failed, text)),
+        }
+    }
+
+    pub fn run(mut self) -> Result<TestReport, Box<dyn Error>> {
+        let mut cycles = 0;
+        while cycles < self.cycle_budget {
+            self.emulator.tick_to_next_frame()?;
+            cycles += CYCLES_PER_FRAME;
+
+            if let Some((outcome, output)) = self.outcome() {
+                return Ok(TestReport {
+                    rom: self.rom,
+                    outcome,
+                    output,
+                    cycles,
+                });
+            }
+        }
+
+        let output = match self.memory_result() {
+            Some((_, text)) => text,
+            None => self.serial_text(),
+        };
+        Ok(TestReport {
+            rom: self.rom,
+            outcome: TestOutcome::TimedOut,
+            output,
+            cycles,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Runs every ROM in `dir`, printing a line per ROM before failing on any that didn't pass
+    fn run_suite(dir: &str) {
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "gb"))
+            .collect();
+        paths.sort();
+
+        let mut failed = Vec::new();
+        for path in paths {
+            let report = TestRunner::new(path.to_str().unwrap())
+                .and_then(TestRunner::run)
+                .unwrap();
+            println!("{report}");
+            if report.outcome != TestOutcome::Passed {
+                failed.push(report.rom);
+            }
+        }
+        assert!(failed.is_empty(), "Failed ROMs: {}", failed.join(", "));
+    }
+
+    #[test]
+    fn cpu_instrs() {
+        run_suite("./roms/tests/cpu_instrs/individual");
+    }
+
+    #[test]
+    fn instr_timing() {
+        run_suite("./roms/tests/instr_timing");
+    }
+
+    #[test]
+    fn mem_timing() {
+        run_suite("./roms/tests/mem_timing/individual");
+    }
+
+    #[test]
+    fn dmg_sound() {
+        run_suite("./roms/tests/dmg_sound/rom_singles");
+    }
+
+    #[test]
+    fn halt_bug() {
+        let report = TestRunner::new("./roms/tests/halt_bug.gb")
+            .and_then(TestRunner::run)
+            .unwrap();
+        println!("{report}");
+        assert_eq!(report.outcome, TestOutcome::Passed);
+    }
+}
@@ -0,0 +1,213 @@
+//! Compares what test ROMs draw against reference screenshots
+//!
+//! References are the DMG screenshots published alongside dmg-acid2 and the mealybug tearoom
+//! tests, saved as `expected/<rom name>.png` next to the ROM
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    emulator::{cartridge::Cartridge, DMGBus, Emulator, SCREEN_HEIGHT, SCREEN_WIDTH},
+    GRAY_PALETTE,
+};
+
+// Test ROMs execute LD B,B once the screen is ready to be checked
+const LD_B_B: u8 = 0x40;
+const MAX_INSTRUCTIONS: usize = 50_000_000;
+const CANONICAL_GREYS: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+const DIFF_DIR: &str = "./target/screenshot_diffs";
+
+/// Screenshots live in an `expected` directory next to the ROM, named after it
+pub fn expected_path(rom_path: &Path) -> PathBuf {
+    let name = rom_path.file_stem().unwrap().to_string_lossy();
+    rom_path
+        .parent()
+        .unwrap()
+        .join("expected")
+        .join(format!("{name}.png"))
+}
+
+// Snaps a grey to the closest of the four shades so slightly different references still match
+fn shade_of_grey(grey: u8) -> u8 {
+    (0..4)
+        .min_by_key(|shade| CANONICAL_GREYS[*shade as usize].abs_diff(grey))
+        .unwrap()
+}
+
+/// Runs the ROM until it hits `LD B,B` and returns the screen as shades 0-3
+pub fn capture(rom_path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut emulator = Emulator::<DMGBus>::new()
+        .with_palette(GRAY_PALETTE)
+        .with_rom(Cartridge::from(rom_path.to_str().unwrap())?)?;
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        let (pc, opcode) = emulator.next_instruction();
+        // The boot rom lives below 0x100 and doesn't use the breakpoint
+        if pc >= 0x100 && opcode == LD_B_B {
+            return screen_shades(&emulator);
+        }
+        emulator.tick_instr()?;
+    }
+    Err("ROM never reached the LD B,B breakpoint".into())
+}
+
+fn screen_shades(emulator: &Emulator<DMGBus>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let colors = [
+        GRAY_PALETTE.0,
+        GRAY_PALETTE.1,
+        GRAY_PALETTE.2,
+        GRAY_PALETTE.3,
+    ];
+    emulator
+        .frame()
+        .raw()
+        .iter()
+        .map(|color| {
+            colors
+                .iter()
+                .position(|c| c == color)
+                .map(|shade| shade as u8)
+                .ok_or_else(|| format!("{color:#08x} is not a palette color").into())
+        })
+        .collect()
+}
+
+/// Loads a reference screenshot as shades 0-3
+pub fn load_expected(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size().ok_or("PNG is too large")?];
+    let info = reader.next_frame(&mut buf)?;
+
+    if (info.width as usize, info.height as usize) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        return Err(format!(
+            "Expected a 160x144 image, got {}x{}",
+            info.width, info.height
+        )
+        .into());
+    }
+
+    let samples = info.color_type.samples();
+    Ok(buf[..info.buffer_size()]
+        .chunks(samples)
+        .map(|pixel| {
+            let grey = if samples >= 3 {
+                (u16::from(pixel[0]) + u16::from(pixel[1]) + u16::from(pixel[2])) / 3
+            } else {
+                u16::from(pixel[0])
+            };
+            shade_of_grey(grey as u8)
+        })
+        .collect())
+}
+
+/// Writes the expected screen with every mismatching pixel highlighted in red
+fn write_diff(path: &Path, actual: &[u8], expected: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut rgb = Vec::with_capacity(actual.len() * 3);
+    for (actual, expected) in actual.iter().zip(expected) {
+        if actual == expected {
+            let grey = CANONICAL_GREYS[*expected as usize] / 2 + 0x40;
+            rgb.extend([grey, grey, grey]);
+        } else {
+            rgb.extend([0xFF, 0x00, 0x00]);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&rgb)?;
+    Ok(())
+}
+
+/// Compares a screen against its reference, writing a diff image if they differ
+pub fn compare(name: &str, actual: &[u8], expected: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mismatches = actual.iter().zip(expected).filter(|(a, e)| a != e).count();
+    if mismatches == 0 {
+        return Ok(());
+    }
+
+    fs::create_dir_all(DIFF_DIR)?;
+    let diff_path = Path::new(DIFF_DIR).join(format!("{name}.png"));
+    write_diff(&diff_path, actual, expected)?;
+    Err(format!(
+        "{mismatches} of {} pixels differ, diff written to {}",
+        expected.len(),
+        diff_path.display()
+    )
+    .into())
+}
+
+/// Captures the ROM and checks it against `expected_path`
+pub fn check_rom(rom_path: &Path) -> Result<(), Box<dyn Error>> {
+    let expected = load_expected(&expected_path(rom_path))
+        .map_err(|e| format!("Unable to load reference screenshot: {e}"))?;
+    let actual = capture(rom_path)?;
+    let name = rom_path.file_stem().unwrap().to_string_lossy();
+    compare(&name, &actual, &expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_roms(paths: Vec<PathBuf>) {
+        let mut failed = Vec::new();
+        for path in paths {
+            match check_rom(&path) {
+                Ok(()) => println!("{}: Passed", path.display()),
+                Err(e) => {
+                    println!("{}: {e}", path.display());
+                    failed.push(path.display().to_string());
+                }
+            }
+        }
+        assert!(failed.is_empty(), "Failed ROMs: {}", failed.join(", "));
+    }
+
+    #[test]
+    fn mismatches_write_diff_image() {
+        let expected = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let mut actual = expected.clone();
+        actual[0] = 3;
+
+        assert!(compare("identical", &expected, &expected).is_ok());
+        let error = compare("one_pixel", &actual, &expected).unwrap_err();
+        assert!(error.to_string().starts_with("1 of 23040 pixels differ"));
+
+        let diff_path = Path::new(DIFF_DIR).join("one_pixel.png");
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(diff_path).unwrap()));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut buf).unwrap();
+        assert_eq!(&buf[..6], &[0xFF, 0x00, 0x00, 0xBF, 0xBF, 0xBF]);
+    }
+
+    #[test]
+    #[ignore = "needs the published reference-dmg.png saved as roms/tests/expected/dmg-acid2.png"]
+    fn dmg_acid2() {
+        check_roms(vec![PathBuf::from("./roms/tests/dmg-acid2.gb")]);
+    }
+
+    #[test]
+    #[ignore = "needs the published DMG screenshots in roms/tests/mealybug-tearoom-tests/expected"]
+    fn mealybug_tearoom() {
+        let mut paths: Vec<_> = fs::read_dir("./roms/tests/mealybug-tearoom-tests")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "gb"))
+            .collect();
+        paths.sort();
+        check_roms(paths);
+    }
+}
@@ -10,11 +10,13 @@ impl FrameBuffer {
         }
     }
 
+    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.buf.is_empty()
     }
 
     // read single value at index
+    #[allow(dead_code)]
     pub fn read(&self, index: usize) -> u32 {
         self.buf[index]
     }
@@ -25,6 +27,7 @@ impl FrameBuffer {
     }
 
     // get ref to u32 vec
+    #[allow(dead_code)]
     pub fn raw(&self) -> &Vec<u32> {
         &self.buf
     }